@@ -0,0 +1,709 @@
+use crate::database::Database;
+use crate::fsrs::FSRSScheduler;
+use crate::models::*;
+use crate::sir_scheduler::SirScheduler;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use rusqlite::{Connection, OpenFlags};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Separator between note fields in `notes.flds`.
+const FIELD_SEPARATOR: char = '\u{1f}';
+/// `Item.source` prefix; the rest is the note GUID, used to skip re-imports.
+/// Items without one are exported under their id as the GUID.
+const SOURCE_PREFIX: &str = "anki:";
+/// Note type kind for cloze models in `col.models`.
+const MODEL_CLOZE: i32 = 1;
+
+#[derive(Deserialize)]
+struct AnkiModel {
+    #[serde(rename = "type", default)]
+    kind: i32,
+}
+
+#[derive(Deserialize)]
+struct AnkiDeck {
+    name: String,
+}
+
+struct Note {
+    id: i64,
+    guid: String,
+    model_id: i64,
+    tags: Vec<String>,
+    fields: Vec<String>,
+}
+
+struct Card {
+    id: i64,
+    note_id: i64,
+    deck_id: i64,
+    ord: i32,
+}
+
+struct Review {
+    id: i64,
+    card_id: i64,
+    ease: i32,
+    time_ms: i64,
+}
+
+/// The extracted collection file, removed when dropped.
+struct TempCollection(PathBuf);
+
+impl Drop for TempCollection {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Copies the SQLite collection out of the `.apkg` zip. Anki 2.1.50+ writes a
+/// zstd-compressed `collection.anki21b` that we cannot read; such decks must be
+/// exported with "Support older Anki versions" ticked.
+fn extract_collection(path: &Path) -> Result<TempCollection> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut archive = zip::ZipArchive::new(file).context("File is not an Anki package")?;
+    let names: Vec<String> = archive.file_names().map(String::from).collect();
+
+    let entry = if names.iter().any(|n| n == "collection.anki21") {
+        "collection.anki21"
+    } else if names.iter().any(|n| n == "collection.anki21b") {
+        bail!("This deck uses the newer Anki package format; re-export it with \"Support older Anki versions\" enabled");
+    } else if names.iter().any(|n| n == "collection.anki2") {
+        "collection.anki2"
+    } else {
+        bail!("Anki package contains no collection");
+    };
+
+    let target = TempCollection(std::env::temp_dir().join(format!("gapfinder-anki-{}.anki2", Uuid::new_v4())));
+    let mut out = File::create(&target.0)?;
+    io::copy(&mut archive.by_name(entry)?, &mut out)?;
+    Ok(target)
+}
+
+fn from_millis(ms: i64) -> DateTime<Utc> {
+    Utc.timestamp_millis_opt(ms).single().unwrap_or_else(Utc::now)
+}
+
+/// Reduces an Anki field to plain text: block tags become line breaks, other
+/// tags and `[sound:...]` references are dropped, common entities are decoded.
+fn html_to_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut rest = html;
+    while let Some(start) = rest.find(['<', '[']) {
+        text.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        if tail.starts_with('<') {
+            let end = tail.find('>').map(|i| i + 1).unwrap_or(tail.len());
+            let tag = tail[1..end].trim_start_matches('/').to_lowercase();
+            let name = tag.split(|c: char| !c.is_ascii_alphanumeric()).next().unwrap_or("");
+            if matches!(name, "br" | "div" | "p" | "li" | "tr") {
+                text.push('\n');
+            }
+            rest = &tail[end..];
+        } else if tail.starts_with("[sound:") {
+            let end = tail.find(']').map(|i| i + 1).unwrap_or(tail.len());
+            rest = &tail[end..];
+        } else {
+            text.push('[');
+            rest = &tail[1..];
+        }
+    }
+    text.push_str(rest);
+
+    let decoded = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&");
+
+    decoded
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Turns `{{c1::answer::hint}}` markers into the `{{}}` blanks used by cloze
+/// stems. Each occurrence is its own blank; repeats of an ordinal get a suffix
+/// (`c1`, `c1-2`).
+fn parse_cloze(text: &str) -> (String, Vec<ClozeBlanks>) {
+    let mut stem = String::new();
+    let mut blanks: Vec<ClozeBlanks> = Vec::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find("{{c") {
+        let tail = &rest[start + 3..];
+        let digits: String = tail.chars().take_while(|c| c.is_ascii_digit()).collect();
+        let body = &tail[digits.len()..];
+        let end = body.find("}}");
+        match (digits.is_empty(), body.strip_prefix("::"), end) {
+            (false, Some(inner), Some(end)) => {
+                let inner = &inner[..end - 2];
+                let answer = inner.split("::").next().unwrap_or("").trim();
+                let ordinal = format!("c{}", digits);
+                let repeats = blanks.iter().filter(|b| b.id.split('-').next() == Some(ordinal.as_str())).count();
+                let id = if repeats == 0 { ordinal } else { format!("{}-{}", ordinal, repeats + 1) };
+
+                stem.push_str(&rest[..start]);
+                stem.push_str("{{}}");
+                blanks.push(ClozeBlanks { id, correct_answer: answer.to_string() });
+                rest = &body[end + 2..];
+            }
+            _ => {
+                stem.push_str(&rest[..start + 3]);
+                rest = tail;
+            }
+        }
+    }
+    stem.push_str(rest);
+    (stem, blanks)
+}
+
+/// Maps a note to an item type, stem and explanation. Cloze notes keep their
+/// "Back Extra" field as the explanation; other note types are read as
+/// front/back with any further fields as the explanation.
+fn note_to_item(note: &Note, model: &AnkiModel) -> Option<(String, ItemType, String)> {
+    let fields: Vec<String> = note.fields.iter().map(|f| html_to_text(f)).collect();
+    let extra = |from: usize| fields.iter().skip(from).filter(|f| !f.is_empty()).cloned().collect::<Vec<_>>().join("\n");
+
+    if model.kind == MODEL_CLOZE {
+        let (stem, blanks) = parse_cloze(fields.first()?);
+        if blanks.is_empty() {
+            return None;
+        }
+        return Some((stem, ItemType::Cloze { blanks }, extra(1)));
+    }
+
+    let front = fields.first().filter(|f| !f.is_empty())?;
+    let back = fields.get(1).filter(|f| !f.is_empty())?;
+    Some((front.clone(), ItemType::FreeRecall { correct_answer: back.clone() }, extra(2)))
+}
+
+/// Replays a note's review log through both schedulers, exactly as
+/// `submit_attempt` would have, so the imported item resumes where Anki left
+/// off. Anki's ease buttons map onto confidence such that the FSRS rating is
+/// the ease itself (Again 1, Hard 2, Good 3, Easy 4).
+fn replay(item_id: &str, reviews: &[&Review], fsrs: &FSRSScheduler, sir: &SirScheduler) -> Vec<Attempt> {
+    let mut attempts: Vec<Attempt> = Vec::with_capacity(reviews.len());
+    for review in reviews {
+        let is_correct = review.ease > 1;
+        let label = ["Again", "Hard", "Good", "Easy"][(review.ease - 1) as usize];
+        let mut attempt = Attempt::new(
+            item_id.to_string(),
+            None,
+            label.to_string(),
+            is_correct,
+            review.ease,
+            review.time_ms,
+        );
+        attempt.attempted_at = from_millis(review.id);
+
+        if let Some(previous) = attempts.last() {
+            attempt.sir_phase = previous.sir_phase.clone();
+            attempt.stability = previous.stability;
+            attempt.difficulty = previous.difficulty;
+            attempt.review_state = previous.review_state.clone();
+            attempt.elapsed_days = (attempt.attempted_at - previous.attempted_at).num_days().max(0) as i32;
+        }
+
+        sir.schedule(&mut attempt, None);
+        fsrs.schedule(&mut attempt, FSRSScheduler::rating(is_correct, review.ease));
+        attempts.push(attempt);
+    }
+    attempts
+}
+
+/// Imports an `.apkg`: notes become items, decks become concepts (top-level
+/// deck as the domain, leaf deck as the name), note tags are added to the
+/// concept's tags, and the review log becomes attempt history. Everything is
+/// written in one transaction.
+pub fn import_apkg(db: &Database, fsrs: &FSRSScheduler, sir: &SirScheduler, path: &Path) -> Result<AnkiImportReport> {
+    let collection = extract_collection(path)?;
+    let conn = Connection::open_with_flags(&collection.0, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .context("Failed to open Anki collection")?;
+
+    let (models_json, decks_json): (String, String) =
+        conn.query_row("SELECT models, decks FROM col", [], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    let models: HashMap<String, AnkiModel> = serde_json::from_str(&models_json).context("Unreadable note types")?;
+    let decks: HashMap<String, AnkiDeck> = serde_json::from_str(&decks_json).context("Unreadable decks")?;
+
+    let notes = conn
+        .prepare("SELECT id, guid, mid, tags, flds FROM notes ORDER BY id")?
+        .query_map([], |row| {
+            Ok(Note {
+                id: row.get(0)?,
+                guid: row.get(1)?,
+                model_id: row.get(2)?,
+                tags: row.get::<_, String>(3)?.split_whitespace().map(String::from).collect(),
+                fields: row.get::<_, String>(4)?.split(FIELD_SEPARATOR).map(String::from).collect(),
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Cards in filtered decks keep their home deck in `odid`
+    let cards = conn
+        .prepare("SELECT id, nid, CASE WHEN odid != 0 THEN odid ELSE did END, ord FROM cards ORDER BY nid, ord")?
+        .query_map([], |row| {
+            Ok(Card { id: row.get(0)?, note_id: row.get(1)?, deck_id: row.get(2)?, ord: row.get(3)? })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Manual reschedules are logged with ease 0 and are not answers
+    let reviews = conn
+        .prepare("SELECT id, cid, ease, time FROM revlog WHERE ease BETWEEN 1 AND 4 ORDER BY id")?
+        .query_map([], |row| {
+            Ok(Review { id: row.get(0)?, card_id: row.get(1)?, ease: row.get(2)?, time_ms: row.get(3)? })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut cards_by_note: HashMap<i64, Vec<&Card>> = HashMap::new();
+    for card in &cards {
+        cards_by_note.entry(card.note_id).or_default().push(card);
+    }
+    let card_notes: HashMap<i64, i64> = cards.iter().map(|c| (c.id, c.note_id)).collect();
+    let mut reviews_by_note: HashMap<i64, Vec<&Review>> = HashMap::new();
+    for review in &reviews {
+        if let Some(note_id) = card_notes.get(&review.card_id) {
+            reviews_by_note.entry(*note_id).or_default().push(review);
+        }
+    }
+
+    // Notes exported from here carry the item id as their GUID
+    let mut imported: HashSet<String> = HashSet::new();
+    for item in db.get_all_items()? {
+        imported.extend(item.source);
+        imported.insert(item.id);
+    }
+    let mut concepts: HashMap<(String, String), Concept> = db
+        .get_all_concepts()?
+        .into_iter()
+        .map(|c| ((c.name.clone(), c.domain.clone()), c))
+        .collect();
+    let mut touched: Vec<(String, String)> = Vec::new();
+
+    let mut report = AnkiImportReport::default();
+    let mut data = CollectionData::default();
+
+    for note in &notes {
+        let source = format!("{}{}", SOURCE_PREFIX, note.guid);
+        if imported.contains(&source) || imported.contains(&note.guid) {
+            report.notes_skipped += 1;
+            continue;
+        }
+        let Some((stem, item_type, explanation)) =
+            models.get(&note.model_id.to_string()).and_then(|model| note_to_item(note, model))
+        else {
+            report.notes_unsupported += 1;
+            continue;
+        };
+
+        let deck_name = cards_by_note
+            .get(&note.id)
+            .and_then(|cards| cards.iter().min_by_key(|c| c.ord))
+            .and_then(|card| decks.get(&card.deck_id.to_string()))
+            .map(|deck| deck.name.clone())
+            .unwrap_or_else(|| "Default".to_string());
+        let path: Vec<&str> = deck_name.split("::").map(str::trim).collect();
+        let key = (path[path.len() - 1].to_string(), path[0].to_string());
+
+        let concept = concepts.entry(key.clone()).or_insert_with(|| {
+            report.concepts_created += 1;
+            let mut concept = Concept::new(key.0.clone(), key.1.clone());
+            if path.len() > 2 {
+                concept.subdomain = Some(path[1..path.len() - 1].join(" :: "));
+            }
+            concept
+        });
+        for tag in &note.tags {
+            if !concept.tags.contains(tag) {
+                concept.tags.push(tag.clone());
+                concept.updated_at = Utc::now();
+            }
+        }
+        if !touched.contains(&key) {
+            touched.push(key);
+        }
+
+        let mut item = Item::new(stem, item_type, vec![concept.id.clone()], explanation);
+        item.source = Some(source);
+        item.created_at = from_millis(note.id);
+
+        let attempts = replay(&item.id, reviews_by_note.get(&note.id).map(Vec::as_slice).unwrap_or(&[]), fsrs, sir);
+        report.attempts_created += attempts.len();
+        data.attempts.extend(attempts);
+        data.items.push(item);
+    }
+
+    report.items_created = data.items.len();
+    data.concepts = touched.iter().filter_map(|key| concepts.remove(key)).collect();
+    db.restore_collection(&data, RestoreMode::Merge)?;
+    Ok(report)
+}
+
+// Fixed ids so repeated exports reuse the same note types in the user's collection
+const BASIC_MODEL_ID: i64 = 1_700_000_000_001;
+const CLOZE_MODEL_ID: i64 = 1_700_000_000_002;
+const DEFAULT_DECK_ID: i64 = 1;
+
+/// Legacy (schema 11) collection layout, which every Anki version still imports.
+const COLLECTION_SCHEMA: &str = "
+    CREATE TABLE col (id integer primary key, crt integer not null, mod integer not null, scm integer not null,
+        ver integer not null, dty integer not null, usn integer not null, ls integer not null, conf text not null,
+        models text not null, decks text not null, dconf text not null, tags text not null);
+    CREATE TABLE notes (id integer primary key, guid text not null, mid integer not null, mod integer not null,
+        usn integer not null, tags text not null, flds text not null, sfld integer not null, csum integer not null,
+        flags integer not null, data text not null);
+    CREATE TABLE cards (id integer primary key, nid integer not null, did integer not null, ord integer not null,
+        mod integer not null, usn integer not null, type integer not null, queue integer not null, due integer not null,
+        ivl integer not null, factor integer not null, reps integer not null, lapses integer not null,
+        left integer not null, odue integer not null, odid integer not null, flags integer not null, data text not null);
+    CREATE TABLE revlog (id integer primary key, cid integer not null, usn integer not null, ease integer not null,
+        ivl integer not null, lastIvl integer not null, factor integer not null, time integer not null, type integer not null);
+    CREATE TABLE graves (usn integer not null, oid integer not null, type integer not null);
+    CREATE INDEX ix_notes_usn ON notes (usn);
+    CREATE INDEX ix_cards_usn ON cards (usn);
+    CREATE INDEX ix_revlog_usn ON revlog (usn);
+    CREATE INDEX ix_cards_nid ON cards (nid);
+    CREATE INDEX ix_cards_sched ON cards (did, queue, due);
+    CREATE INDEX ix_revlog_cid ON revlog (cid);
+    CREATE INDEX ix_notes_csum ON notes (csum);";
+
+fn note_type(id: i64, name: &str, kind: i32, fields: &[&str], qfmt: &str, afmt: &str) -> serde_json::Value {
+    let flds: Vec<serde_json::Value> = fields
+        .iter()
+        .enumerate()
+        .map(|(ord, name)| {
+            serde_json::json!({
+                "name": name, "ord": ord, "sticky": false, "rtl": false, "font": "Arial", "size": 20, "media": []
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "id": id,
+        "name": name,
+        "type": kind,
+        "mod": Utc::now().timestamp(),
+        "usn": -1,
+        "sortf": 0,
+        "did": DEFAULT_DECK_ID,
+        "tmpls": [{
+            "name": if kind == MODEL_CLOZE { "Cloze" } else { "Card 1" },
+            "ord": 0, "qfmt": qfmt, "afmt": afmt, "bqfmt": "", "bafmt": "", "did": null, "bfont": "", "bsize": 0
+        }],
+        "flds": flds,
+        "css": ".card { font-family: arial; font-size: 20px; text-align: center; color: black; background-color: white; }\n.cloze { font-weight: bold; color: blue; }",
+        "latexPre": "\\documentclass[12pt]{article}\n\\special{papersize=3in,5in}\n\\usepackage[utf8]{inputenc}\n\\usepackage{amssymb,amsmath}\n\\pagestyle{empty}\n\\setlength{\\parindent}{0in}\n\\begin{document}\n",
+        "latexPost": "\\end{document}",
+        "latexsvg": false,
+        "req": [[0, "any", [0]]],
+        "tags": [],
+        "vers": []
+    })
+}
+
+fn deck(id: i64, name: &str) -> serde_json::Value {
+    serde_json::json!({
+        "id": id, "name": name, "mod": Utc::now().timestamp(), "usn": -1, "desc": "", "dyn": 0, "conf": 1,
+        "collapsed": false, "browserCollapsed": false, "extendNew": 0, "extendRev": 0,
+        "newToday": [0, 0], "revToday": [0, 0], "lrnToday": [0, 0], "timeToday": [0, 0]
+    })
+}
+
+fn default_deck_config() -> serde_json::Value {
+    serde_json::json!({
+        "1": {
+            "id": 1, "name": "Default", "mod": 0, "usn": 0, "maxTaken": 60, "autoplay": true, "timer": 0,
+            "replayq": true, "dyn": false,
+            "new": { "delays": [1.0, 10.0], "ints": [1, 4, 0], "initialFactor": 2500, "order": 1, "perDay": 20, "bury": false },
+            "rev": { "perDay": 200, "ease4": 1.3, "ivlFct": 1.0, "maxIvl": 36500, "hardFactor": 1.2, "bury": false },
+            "lapse": { "delays": [10.0], "mult": 0.0, "minInt": 1, "leechFails": 8, "leechAction": 1 }
+        }
+    })
+}
+
+/// Escapes plain text for an Anki field, which is rendered as HTML.
+fn text_to_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\n', "<br>")
+}
+
+/// Anki's duplicate-check hash: the first 8 hex digits of the SHA-1 of the
+/// sort field's text.
+fn field_checksum(field: &str) -> i64 {
+    let digest = sha1_smol::Sha1::from(html_to_text(field)).digest().to_string();
+    i64::from_str_radix(&digest[..8], 16).unwrap_or(0)
+}
+
+/// Next free id at or after `preferred`; Anki ids are millisecond timestamps.
+fn unique_id(preferred: i64, used: &mut HashSet<i64>) -> i64 {
+    let mut id = preferred;
+    while !used.insert(id) {
+        id += 1;
+    }
+    id
+}
+
+/// Blank ordinal from ids such as `c2` or `c2-3`, falling back to position.
+fn cloze_ordinal(blank: &ClozeBlanks, index: usize) -> i32 {
+    blank
+        .id
+        .strip_prefix('c')
+        .and_then(|rest| rest.split('-').next())
+        .and_then(|n| n.parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(index as i32 + 1)
+}
+
+/// Maps an item to (model id, fields, card ordinals). Calculation and case
+/// items have no equivalent note type.
+fn item_to_note(item: &Item) -> Option<(i64, Vec<String>, Vec<i32>)> {
+    match &item.item_type {
+        ItemType::FreeRecall { correct_answer } => Some((
+            BASIC_MODEL_ID,
+            vec![
+                text_to_html(&item.stem),
+                text_to_html(&correct_answer.replace('|', " / ")),
+                text_to_html(&item.explanation),
+            ],
+            vec![0],
+        )),
+        ItemType::Mcq { options } => {
+            let choices: Vec<String> = options.iter().map(|o| format!("• {}", text_to_html(&o.text))).collect();
+            let keyed: Vec<&str> = options.iter().filter(|o| o.is_correct).map(|o| o.text.as_str()).collect();
+            Some((
+                BASIC_MODEL_ID,
+                vec![
+                    format!("{}<br><br>{}", text_to_html(&item.stem), choices.join("<br>")),
+                    text_to_html(&keyed.join(", ")),
+                    text_to_html(&item.explanation),
+                ],
+                vec![0],
+            ))
+        }
+        ItemType::Cloze { blanks } => {
+            let parts: Vec<&str> = item.stem.split("{{}}").collect();
+            if blanks.is_empty() || parts.len() != blanks.len() + 1 {
+                return None;
+            }
+            let mut text = text_to_html(parts[0]);
+            let mut ordinals: Vec<i32> = Vec::new();
+            for (i, blank) in blanks.iter().enumerate() {
+                let ordinal = cloze_ordinal(blank, i);
+                let answer = blank.correct_answer.split('|').next().unwrap_or("").trim();
+                text.push_str(&format!("{{{{c{}::{}}}}}", ordinal, text_to_html(answer)));
+                text.push_str(&text_to_html(parts[i + 1]));
+                if !ordinals.contains(&(ordinal - 1)) {
+                    ordinals.push(ordinal - 1);
+                }
+            }
+            ordinals.sort_unstable();
+            Some((CLOZE_MODEL_ID, vec![text, text_to_html(&item.explanation)], ordinals))
+        }
+        ItemType::Calculation { .. } | ItemType::CaseVignette { .. } => None,
+    }
+}
+
+/// Writes the chosen items to an `.apkg`. `concept_ids` and `item_ids` are
+/// combined; when both are empty the whole collection is exported. Each note
+/// goes in the deck `domain::subdomain::name` of its first concept, and the
+/// tags of all its concepts become Anki tags. With `include_history` the
+/// attempts become revlog entries on the note's first card and the card is
+/// scheduled from the item's current review state.
+pub fn export_apkg(
+    db: &Database,
+    path: &Path,
+    concept_ids: &[String],
+    item_ids: &[String],
+    include_history: bool,
+) -> Result<AnkiExportReport> {
+    let items: Vec<Item> = if concept_ids.is_empty() && item_ids.is_empty() {
+        db.get_all_items()?
+    } else {
+        let mut selected: Vec<Item> = Vec::new();
+        for concept_id in concept_ids {
+            selected.extend(db.get_items_by_concept(concept_id)?);
+        }
+        for item_id in item_ids {
+            selected.extend(db.get_item(item_id)?);
+        }
+        let mut seen = HashSet::new();
+        selected.retain(|item| seen.insert(item.id.clone()));
+        selected
+    };
+    let concepts: HashMap<String, Concept> = db.get_all_concepts()?.into_iter().map(|c| (c.id.clone(), c)).collect();
+
+    let now = Utc::now();
+    let today = now.date_naive();
+    let crt = today.and_hms_opt(0, 0, 0).unwrap().and_utc();
+
+    let target = TempCollection(std::env::temp_dir().join(format!("gapfinder-anki-{}.anki2", Uuid::new_v4())));
+    let mut conn = Connection::open(&target.0)?;
+    conn.execute_batch(COLLECTION_SCHEMA)?;
+
+    let mut report = AnkiExportReport::default();
+    let mut decks: HashMap<String, i64> = HashMap::new();
+    let mut used_ids: HashSet<i64> = HashSet::new();
+    let mut all_tags: HashSet<String> = HashSet::new();
+
+    let tx = conn.transaction()?;
+    for (position, item) in items.iter().enumerate() {
+        let Some((model_id, fields, ordinals)) = item_to_note(item) else {
+            report.items_skipped += 1;
+            continue;
+        };
+
+        let item_concepts: Vec<&Concept> = item.concept_ids.iter().filter_map(|id| concepts.get(id)).collect();
+        let deck_id = match item_concepts.first() {
+            Some(concept) => {
+                let mut path = vec![concept.domain.as_str()];
+                path.extend(concept.subdomain.as_deref());
+                path.push(concept.name.as_str());
+                let name = path.join("::");
+                let next_id = now.timestamp_millis() + decks.len() as i64;
+                *decks.entry(name).or_insert(next_id)
+            }
+            None => DEFAULT_DECK_ID,
+        };
+        let mut tags: Vec<String> = Vec::new();
+        for tag in item_concepts.iter().flat_map(|c| c.tags.iter()) {
+            let tag = tag.split_whitespace().collect::<Vec<_>>().join("_");
+            if !tag.is_empty() && !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        all_tags.extend(tags.iter().cloned());
+
+        let guid = item
+            .source
+            .as_deref()
+            .and_then(|s| s.strip_prefix(SOURCE_PREFIX))
+            .map(String::from)
+            .unwrap_or_else(|| item.id.clone());
+        let note_id = unique_id(item.created_at.timestamp_millis(), &mut used_ids);
+        let sort_field = html_to_text(&fields[0]);
+        tx.execute(
+            "INSERT INTO notes VALUES (?1, ?2, ?3, ?4, -1, ?5, ?6, ?7, ?8, 0, '')",
+            rusqlite::params![
+                note_id,
+                guid,
+                model_id,
+                item.updated_at.timestamp(),
+                if tags.is_empty() { String::new() } else { format!(" {} ", tags.join(" ")) },
+                fields.join(&FIELD_SEPARATOR.to_string()),
+                sort_field,
+                field_checksum(&fields[0])
+            ],
+        )?;
+        report.notes_exported += 1;
+
+        let history: Vec<Attempt> = if include_history {
+            let mut attempts = db.get_attempts_by_item(&item.id)?;
+            attempts.reverse();
+            attempts
+        } else {
+            Vec::new()
+        };
+        let state = if history.is_empty() { None } else { db.get_item_state(&item.id)? };
+
+        for (i, ord) in ordinals.iter().enumerate() {
+            let card_id = unique_id(note_id, &mut used_ids);
+            // Only the first card carries history; the rest start as new cards
+            let scheduled = state.as_ref().filter(|_| i == 0);
+            let (card_type, queue, due, ivl, reps, lapses) = match scheduled {
+                Some(s) => (2, 2, (s.next_review_date.date_naive() - today).num_days(), s.scheduled_days, s.reps, s.lapses),
+                None => (0, 0, position as i64, 0, 0, 0),
+            };
+            tx.execute(
+                "INSERT INTO cards VALUES (?1, ?2, ?3, ?4, ?5, -1, ?6, ?7, ?8, ?9, 2500, ?10, ?11, 0, 0, 0, 0, '')",
+                rusqlite::params![card_id, note_id, deck_id, ord, now.timestamp(), card_type, queue, due, ivl, reps, lapses],
+            )?;
+            report.cards_exported += 1;
+
+            if i == 0 {
+                let mut last_interval = 0;
+                for attempt in &history {
+                    let kind = match attempt.review_state {
+                        ReviewState::New | ReviewState::Learning => 0,
+                        ReviewState::Review => 1,
+                        ReviewState::Relearning => 2,
+                    };
+                    tx.execute(
+                        "INSERT INTO revlog VALUES (?1, ?2, -1, ?3, ?4, ?5, 2500, ?6, ?7)",
+                        rusqlite::params![
+                            unique_id(attempt.attempted_at.timestamp_millis(), &mut used_ids),
+                            card_id,
+                            FSRSScheduler::rating(attempt.is_correct, attempt.confidence),
+                            attempt.scheduled_days,
+                            last_interval,
+                            attempt.time_spent_ms.clamp(0, 60_000),
+                            kind
+                        ],
+                    )?;
+                    last_interval = attempt.scheduled_days;
+                    report.reviews_exported += 1;
+                }
+            }
+        }
+    }
+
+    let mut deck_json = serde_json::Map::new();
+    deck_json.insert(DEFAULT_DECK_ID.to_string(), deck(DEFAULT_DECK_ID, "Default"));
+    for (name, id) in &decks {
+        deck_json.insert(id.to_string(), deck(*id, name));
+    }
+    let models = serde_json::json!({
+        BASIC_MODEL_ID.to_string(): note_type(
+            BASIC_MODEL_ID, "GapFinder Basic", 0, &["Front", "Back", "Explanation"],
+            "{{Front}}", "{{FrontSide}}<hr id=answer>{{Back}}{{#Explanation}}<br><br>{{Explanation}}{{/Explanation}}"
+        ),
+        CLOZE_MODEL_ID.to_string(): note_type(
+            CLOZE_MODEL_ID, "GapFinder Cloze", MODEL_CLOZE, &["Text", "Back Extra"],
+            "{{cloze:Text}}", "{{cloze:Text}}<br>{{Back Extra}}"
+        ),
+    });
+    let conf = serde_json::json!({
+        "nextPos": items.len() + 1, "estTimes": true, "activeDecks": [DEFAULT_DECK_ID], "sortType": "noteFld",
+        "timeLim": 0, "sortBackwards": false, "addToCur": true, "curDeck": DEFAULT_DECK_ID, "newSpread": 0,
+        "dueCounts": true, "curModel": BASIC_MODEL_ID.to_string(), "collapseTime": 1200
+    });
+    let tags: serde_json::Map<String, serde_json::Value> =
+        all_tags.into_iter().map(|t| (t, serde_json::json!(0))).collect();
+
+    tx.execute(
+        "INSERT INTO col VALUES (1, ?1, ?2, ?2, 11, 0, 0, 0, ?3, ?4, ?5, ?6, ?7)",
+        rusqlite::params![
+            crt.timestamp(),
+            now.timestamp_millis(),
+            conf.to_string(),
+            models.to_string(),
+            serde_json::Value::Object(deck_json).to_string(),
+            default_deck_config().to_string(),
+            serde_json::Value::Object(tags).to_string()
+        ],
+    )?;
+    tx.commit()?;
+    drop(conn);
+
+    let file = File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut zip = zip::ZipWriter::new(file);
+    let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    zip.start_file("collection.anki2", options)?;
+    io::copy(&mut File::open(&target.0)?, &mut zip)?;
+    zip.start_file("media", options)?;
+    io::Write::write_all(&mut zip, b"{}")?;
+    zip.finish()?;
+
+    Ok(report)
+}
@@ -0,0 +1,95 @@
+use crate::database::Database;
+use crate::models::{ArchiveSummary, CollectionData, RestoreMode};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+
+const FORMAT: &str = "gapfinder-collection";
+const FORMAT_VERSION: u32 = 1;
+
+/// On-disk envelope. `checksum` is the SHA-256 of `data` re-serialized as
+/// compact JSON, so it does not depend on the file's whitespace.
+#[derive(Serialize, Deserialize)]
+struct Archive {
+    format: String,
+    format_version: u32,
+    schema_version: i32,
+    created_at: DateTime<Utc>,
+    checksum: String,
+    data: serde_json::Value,
+}
+
+fn checksum(data: &serde_json::Value) -> Result<String> {
+    let bytes = serde_json::to_vec(data)?;
+    Ok(hex::encode(Sha256::digest(&bytes)))
+}
+
+fn summary(archive: &Archive, data: &CollectionData) -> ArchiveSummary {
+    ArchiveSummary {
+        schema_version: archive.schema_version,
+        created_at: archive.created_at,
+        checksum: archive.checksum.clone(),
+        learning_materials: data.learning_materials.len(),
+        concepts: data.concepts.len(),
+        items: data.items.len(),
+        attempts: data.attempts.len(),
+        sessions: data.sessions.len(),
+    }
+}
+
+/// Writes the whole collection to `path`.
+pub fn export(db: &Database, path: &Path) -> Result<ArchiveSummary> {
+    let collection = db.export_collection()?;
+    let data = serde_json::to_value(&collection)?;
+    let archive = Archive {
+        format: FORMAT.to_string(),
+        format_version: FORMAT_VERSION,
+        schema_version: db.schema_version()?,
+        created_at: Utc::now(),
+        checksum: checksum(&data)?,
+        data,
+    };
+
+    let json = serde_json::to_string_pretty(&archive)?;
+    fs::write(path, json).with_context(|| format!("Failed to write archive to {}", path.display()))?;
+    Ok(summary(&archive, &collection))
+}
+
+/// Reads and verifies the archive at `path`, then restores it. The envelope,
+/// schema version and checksum are all checked before the database is touched.
+/// Archives from an older schema are accepted since they decode into the
+/// current models; archives from a newer one are refused.
+pub fn restore(db: &Database, path: &Path, mode: RestoreMode) -> Result<ArchiveSummary> {
+    let json = fs::read_to_string(path).with_context(|| format!("Failed to read archive {}", path.display()))?;
+    let archive: Archive = serde_json::from_str(&json).context("File is not a GapFinder collection archive")?;
+
+    if archive.format != FORMAT {
+        bail!("File is not a GapFinder collection archive");
+    }
+    if archive.format_version > FORMAT_VERSION {
+        bail!(
+            "Archive format version {} is newer than this app supports ({})",
+            archive.format_version,
+            FORMAT_VERSION
+        );
+    }
+    let running = db.schema_version()?;
+    if archive.schema_version > running {
+        bail!(
+            "Archive was written by schema version {}, but this database is at version {}; please update GapFinder",
+            archive.schema_version,
+            running
+        );
+    }
+    if checksum(&archive.data)? != archive.checksum {
+        bail!("Archive checksum does not match its contents; the file is corrupt or was edited");
+    }
+
+    let data: CollectionData =
+        serde_json::from_value(archive.data.clone()).context("Archive contents are malformed")?;
+    db.restore_collection(&data, mode)?;
+    Ok(summary(&archive, &data))
+}
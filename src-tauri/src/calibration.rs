@@ -0,0 +1,119 @@
+use crate::models::{Attempt, CalibrationAnalysis, CalibrationBin, CalibrationReport, Concept, ConfidenceMapping, Item};
+use anyhow::{bail, Result};
+use std::collections::{BTreeMap, HashMap};
+
+/// A mapping must hold probabilities in [0, 1] that never fall as confidence rises.
+pub fn validate_mapping(mapping: &ConfidenceMapping) -> Result<()> {
+    let p = &mapping.probabilities;
+    if p.iter().any(|v| !(0.0..=1.0).contains(v)) {
+        bail!("Confidence probabilities must be between 0 and 1");
+    }
+    if p.windows(2).any(|w| w[1] < w[0]) {
+        bail!("Confidence probabilities must not decrease as confidence rises");
+    }
+    Ok(())
+}
+
+/// Scores `attempts` as forecasts: each confidence rating is read as the
+/// mapped probability of being correct. Bins are the five confidence ratings,
+/// so every forecast in a bin is identical and the decomposition is exact.
+pub fn report(key: &str, label: &str, attempts: &[&Attempt], mapping: &ConfidenceMapping) -> CalibrationReport {
+    let mut by_confidence: BTreeMap<i32, (i32, i32)> = BTreeMap::new();
+    let mut brier = 0.0;
+    for attempt in attempts {
+        let confidence = attempt.confidence.clamp(1, 5);
+        let outcome = if attempt.is_correct { 1.0 } else { 0.0 };
+        brier += (mapping.probability(confidence) - outcome).powi(2);
+        let (count, correct) = by_confidence.entry(confidence).or_default();
+        *count += 1;
+        *correct += attempt.is_correct as i32;
+    }
+
+    let n = attempts.len() as f64;
+    let base_rate = attempts.iter().filter(|a| a.is_correct).count() as f64 / n.max(1.0);
+    let bins: Vec<CalibrationBin> = by_confidence
+        .into_iter()
+        .map(|(confidence, (count, correct))| CalibrationBin {
+            confidence,
+            forecast: mapping.probability(confidence),
+            observed: correct as f64 / count as f64,
+            count,
+        })
+        .collect();
+
+    let mut result = CalibrationReport {
+        key: key.to_string(),
+        label: label.to_string(),
+        attempts: attempts.len() as i32,
+        brier_score: 0.0,
+        reliability: 0.0,
+        resolution: 0.0,
+        uncertainty: 0.0,
+        overconfidence: 0.0,
+        underconfidence: 0.0,
+        bins,
+    };
+    if attempts.is_empty() {
+        return result;
+    }
+
+    result.brier_score = brier / n;
+    result.uncertainty = base_rate * (1.0 - base_rate);
+    for bin in &result.bins {
+        let weight = bin.count as f64 / n;
+        let gap = bin.forecast - bin.observed;
+        result.reliability += weight * gap.powi(2);
+        result.resolution += weight * (bin.observed - base_rate).powi(2);
+        result.overconfidence += weight * gap.max(0.0);
+        result.underconfidence += weight * (-gap).max(0.0);
+    }
+    result
+}
+
+/// Overall calibration plus one report per concept, domain and item type.
+/// An attempt on an item linked to several concepts counts toward each of
+/// them, and toward each distinct domain once. Groups are largest first.
+pub fn analyze(
+    attempts: &[Attempt],
+    items: &HashMap<String, Item>,
+    concepts: &HashMap<String, Concept>,
+    mapping: &ConfidenceMapping,
+) -> CalibrationAnalysis {
+    let mut by_concept: HashMap<&str, Vec<&Attempt>> = HashMap::new();
+    let mut by_domain: HashMap<&str, Vec<&Attempt>> = HashMap::new();
+    let mut by_item_type: HashMap<&str, Vec<&Attempt>> = HashMap::new();
+
+    for attempt in attempts {
+        let Some(item) = items.get(&attempt.item_id) else { continue };
+        by_item_type.entry(item.item_type.kind()).or_default().push(attempt);
+
+        let mut domains: Vec<&str> = Vec::new();
+        for concept in item.concept_ids.iter().filter_map(|id| concepts.get(id)) {
+            by_concept.entry(concept.id.as_str()).or_default().push(attempt);
+            if !domains.contains(&concept.domain.as_str()) {
+                domains.push(concept.domain.as_str());
+            }
+        }
+        for domain in domains {
+            by_domain.entry(domain).or_default().push(attempt);
+        }
+    }
+
+    let group = |groups: HashMap<&str, Vec<&Attempt>>, label: &dyn Fn(&str) -> String| {
+        let mut reports: Vec<CalibrationReport> = groups
+            .into_iter()
+            .map(|(key, attempts)| report(key, &label(key), &attempts, mapping))
+            .collect();
+        reports.sort_by(|a, b| b.attempts.cmp(&a.attempts).then_with(|| a.label.cmp(&b.label)));
+        reports
+    };
+
+    let all: Vec<&Attempt> = attempts.iter().collect();
+    CalibrationAnalysis {
+        mapping: mapping.clone(),
+        overall: report("all", "All attempts", &all, mapping),
+        by_concept: group(by_concept, &|id| concepts.get(id).map(|c| c.name.clone()).unwrap_or_default()),
+        by_domain: group(by_domain, &|domain| domain.to_string()),
+        by_item_type: group(by_item_type, &|kind| kind.to_string()),
+    }
+}
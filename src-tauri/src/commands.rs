@@ -1,14 +1,32 @@
+use crate::anki;
+use crate::archive;
+use crate::calibration;
+use crate::concept_graph;
 use crate::database::Database;
+use crate::diagnostic;
+use crate::distractor_analysis;
+use crate::error_analysis;
+use crate::exam;
+use crate::focused;
+use crate::formula;
 use crate::fsrs::FSRSScheduler;
-use crate::sir_scheduler::SirScheduler;
+use crate::fsrs_optimizer;
+use crate::grading;
+use crate::irt;
+use crate::item_import;
+use crate::planner;
+use crate::session_report;
+use crate::sir_scheduler::{SirScheduler, TREND_WINDOW_DAYS};
 use crate::models::*;
 use chrono::Utc;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
 use tauri::State;
 
 pub struct AppState {
     pub db: Arc<Database>,
-    pub fsrs: Arc<FSRSScheduler>,
+    pub fsrs: Arc<RwLock<FSRSScheduler>>,
     pub sir: Arc<SirScheduler>,
 }
 
@@ -36,6 +54,45 @@ pub fn delete_concept(state: State<AppState>, id: String) -> Result<(), String>
     state.db.delete_concept(&id).map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+pub fn get_items_by_concept(state: State<AppState>, concept_id: String) -> Result<Vec<Item>, String> {
+    let items = state.db.get_items_by_concept(&concept_id).map_err(|e| e.to_string())?;
+    exam::withhold_open(&state.db, items, Utc::now()).map_err(|e| e.to_string())
+}
+
+/// Rejects edges that would close a prerequisite or part-of cycle.
+#[tauri::command]
+pub fn add_concept_edge(
+    state: State<AppState>,
+    from_concept_id: String,
+    to_concept_id: String,
+    relation: ConceptRelation,
+) -> Result<ConceptEdge, String> {
+    let edges = state.db.get_concept_edges().map_err(|e| e.to_string())?;
+    if let Some(cycle) = concept_graph::find_cycle(&edges, &from_concept_id, &to_concept_id, relation) {
+        return Err(format!("Edge would create a {} cycle: {}", relation.as_str(), cycle.join(" -> ")));
+    }
+
+    let edge = ConceptEdge { from_concept_id, to_concept_id, relation, created_at: Utc::now() };
+    state.db.add_concept_edge(&edge).map_err(|e| e.to_string())?;
+    Ok(edge)
+}
+
+#[tauri::command]
+pub fn remove_concept_edge(
+    state: State<AppState>,
+    from_concept_id: String,
+    to_concept_id: String,
+    relation: ConceptRelation,
+) -> Result<(), String> {
+    state.db.delete_concept_edge(&from_concept_id, &to_concept_id, relation).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_concept_edges(state: State<AppState>) -> Result<Vec<ConceptEdge>, String> {
+    state.db.get_concept_edges().map_err(|e| e.to_string())
+}
+
 // ==================== Item Commands ====================
 
 #[tauri::command]
@@ -46,6 +103,7 @@ pub fn create_item(
     concept_ids: Vec<String>,
     explanation: String,
 ) -> Result<Item, String> {
+    formula::validate_item(&item_type).map_err(|e| e.to_string())?;
     let item = Item::new(stem, item_type, concept_ids, explanation);
     state.db.create_item(&item).map_err(|e| e.to_string())?;
     Ok(item)
@@ -53,11 +111,17 @@ pub fn create_item(
 
 #[tauri::command]
 pub fn get_all_items(state: State<AppState>) -> Result<Vec<Item>, String> {
-    state.db.get_all_items().map_err(|e| e.to_string())
+    let items = state.db.get_all_items().map_err(|e| e.to_string())?;
+    exam::withhold_open(&state.db, items, Utc::now()).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 pub fn update_item(state: State<AppState>, item: Item) -> Result<(), String> {
+    // Items in a running exam are listed without their answers; saving one back would erase them
+    if exam::open_item_ids(&state.db, Utc::now()).map_err(|e| e.to_string())?.contains(&item.id) {
+        return Err("Item is part of an exam in progress".to_string());
+    }
+    formula::validate_item(&item.item_type).map_err(|e| e.to_string())?;
     state.db.update_item(&item).map_err(|e| e.to_string())
 }
 
@@ -66,6 +130,11 @@ pub fn delete_item(state: State<AppState>, id: String) -> Result<(), String> {
     state.db.delete_item(&id).map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+pub fn get_concepts_by_item(state: State<AppState>, item_id: String) -> Result<Vec<Concept>, String> {
+    state.db.get_concepts_by_item(&item_id).map_err(|e| e.to_string())
+}
+
 // ==================== Learning Material Commands ====================
 
 #[tauri::command]
@@ -87,6 +156,91 @@ pub fn get_learning_material(
     state.db.get_learning_material(&id).map_err(|e| e.to_string())
 }
 
+/// Every material, or only those in `domain`.
+#[tauri::command]
+pub fn get_all_learning_materials(
+    state: State<AppState>,
+    domain: Option<String>,
+) -> Result<Vec<LearningMaterial>, String> {
+    match domain {
+        Some(domain) => state.db.get_learning_materials_by_domain(&domain),
+        None => state.db.get_all_learning_materials(),
+    }
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn search_learning_materials(state: State<AppState>, query: String) -> Result<Vec<LearningMaterial>, String> {
+    state.db.search_learning_materials(&query).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn update_learning_material(state: State<AppState>, material: LearningMaterial) -> Result<(), String> {
+    state.db.update_learning_material(&material).map_err(|e| e.to_string())
+}
+
+/// Linked concepts stay, detached from the material.
+#[tauri::command]
+pub fn delete_learning_material(state: State<AppState>, id: String) -> Result<(), String> {
+    state.db.delete_learning_material(&id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_concepts_by_material(state: State<AppState>, material_id: String) -> Result<Vec<Concept>, String> {
+    state.db.get_concepts_by_material(&material_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_items_by_material(state: State<AppState>, material_id: String) -> Result<Vec<Item>, String> {
+    let items = state.db.get_items_by_material(&material_id).map_err(|e| e.to_string())?;
+    exam::withhold_open(&state.db, items, Utc::now()).map_err(|e| e.to_string())
+}
+
+/// Progress for one material, or for every material when `material_id` is
+/// omitted, from the mastery of the concepts that point at it.
+#[tauri::command]
+pub fn get_material_progress(
+    state: State<AppState>,
+    material_id: Option<String>,
+) -> Result<Vec<MaterialProgress>, String> {
+    let materials = match material_id {
+        Some(id) => vec![state.db.get_learning_material(&id).map_err(|e| e.to_string())?
+            .ok_or("Learning material not found")?],
+        None => state.db.get_all_learning_materials().map_err(|e| e.to_string())?,
+    };
+    let concepts = state.db.get_all_concepts().map_err(|e| e.to_string())?;
+    let mut mastery: HashMap<String, ConceptMastery> = compute_concept_mastery(&state)?
+        .into_iter()
+        .map(|m| (m.concept_id.clone(), m))
+        .collect();
+
+    let mut progress = Vec::with_capacity(materials.len());
+    for material in materials {
+        let mut linked: Vec<ConceptMastery> = concepts
+            .iter()
+            .filter(|c| c.learning_material_id.as_deref() == Some(material.id.as_str()))
+            .filter_map(|c| mastery.remove(&c.id))
+            .collect();
+        linked.sort_by(|a, b| a.mastery_score.total_cmp(&b.mastery_score).then_with(|| a.concept_name.cmp(&b.concept_name)));
+
+        let attempted: Vec<&ConceptMastery> = linked.iter().filter(|m| m.attempts > 0).collect();
+        progress.push(MaterialProgress {
+            material_id: material.id,
+            domain: material.domain,
+            concept_count: linked.len() as i32,
+            attempted_concepts: attempted.len() as i32,
+            weak_concepts: attempted.iter().filter(|m| m.mastery_score < concept_graph::WEAK_THRESHOLD).count() as i32,
+            mastery_score: if linked.is_empty() {
+                0.0
+            } else {
+                linked.iter().map(|m| m.mastery_score).sum::<f64>() / linked.len() as f64
+            },
+            concepts: linked,
+        });
+    }
+    Ok(progress)
+}
+
 // ==================== Attempt Commands ====================
 
 #[tauri::command]
@@ -94,61 +248,204 @@ pub fn submit_attempt(
     state: State<AppState>,
     item_id: String,
     session_id: Option<String>,
-    user_answer: String,
-    is_correct: bool,
+    answer: ItemAnswer,
     confidence: i32,
     time_spent_ms: i64,
     metacognitive: Option<MetacognitiveReflection>,
 ) -> Result<Attempt, String> {
-    let mut attempt = Attempt::new(item_id.clone(), session_id, user_answer, is_correct, confidence, time_spent_ms);
-    
+    let item = state.db.get_item(&item_id).map_err(|e| e.to_string())?
+        .ok_or("Item not found")?;
+
+    let session = match &session_id {
+        Some(id) => state.db.get_session(id).map_err(|e| e.to_string())?,
+        None => None,
+    };
+    let exam_session = session.as_ref().filter(|s| matches!(s.session_type, SessionType::Exam { .. }));
+    let in_exam = exam::open_item_ids(&state.db, Utc::now()).map_err(|e| e.to_string())?.contains(&item_id);
+    match exam_session {
+        Some(exam_session) => {
+            exam::check_submission(&state.db, exam_session, &item_id, Utc::now()).map_err(|e| e.to_string())?;
+        }
+        // Answering outside the exam would hand back its grade and move the item's schedule
+        None if in_exam => return Err("Item is part of an exam in progress".to_string()),
+        None => {}
+    }
+
+    let grade = grading::grade(&item, &answer).map_err(|e| e.to_string())?;
+    let is_correct = grade.is_correct;
+
+    let mut attempt = Attempt::new(
+        item_id.clone(),
+        session_id,
+        grading::answer_text(&answer),
+        is_correct,
+        confidence,
+        time_spent_ms,
+    );
+    attempt.score = grade.score;
+    attempt.feedback = grade.feedback;
+
+    let fsrs = state.fsrs.read().unwrap();
+
+    // Continue the SIR ladder and FSRS memory state from the item's last review
+    if let Some(item_state) = state.db.get_item_state(&item_id).map_err(|e| e.to_string())? {
+        attempt.sir_phase = item_state.sir_phase.clone();
+        fsrs.resume(&mut attempt, &item_state);
+    }
+
     // Use SIR scheduler for SIR-based scheduling
     state.sir.schedule(&mut attempt, metacognitive);
     
     // Also keep FSRS compatibility
-    let rating = if is_correct {
-        confidence.min(4).max(2)
-    } else {
-        1
-    };
-    state.fsrs.schedule(&mut attempt, rating);
+    fsrs.schedule(&mut attempt, FSRSScheduler::rating(is_correct, confidence));
     
     state.db.create_attempt(&attempt).map_err(|e| e.to_string())?;
+
+    // Calculation items with variable ranges come back with fresh numbers; a
+    // failed draw keeps the numbers just answered. Exams keep theirs until the
+    // results are out.
+    if !in_exam {
+        let mut next = item.clone();
+        if let Ok(true) = formula::regenerate(&mut next, &mut rand::thread_rng()) {
+            next.updated_at = Utc::now();
+            state.db.update_item(&next).map_err(|e| e.to_string())?;
+        }
+    }
+
+    if let Some(session) = session.as_ref().filter(|s| matches!(s.session_type, SessionType::Diagnostic)) {
+        diagnostic::record(&state.db, session, &item, attempt.attempted_at).map_err(|e| e.to_string())?;
+    }
+
+    // Exams return the grade only with the results, once every item is answered
+    if let Some(exam_session) = exam_session {
+        return exam::finish_submission(&state.db, exam_session, attempt, Utc::now()).map_err(|e| e.to_string());
+    }
     
     Ok(attempt)
 }
 
 #[tauri::command]
 pub fn get_attempts_by_item(state: State<AppState>, item_id: String) -> Result<Vec<Attempt>, String> {
-    state.db.get_attempts_by_item(&item_id).map_err(|e| e.to_string())
+    let attempts = state.db.get_attempts_by_item(&item_id).map_err(|e| e.to_string())?;
+    // Earlier grades would give away the answer to an item in a running exam
+    if exam::open_item_ids(&state.db, Utc::now()).map_err(|e| e.to_string())?.contains(&item_id) {
+        return Ok(attempts.into_iter().map(exam::withhold_grade).collect());
+    }
+    Ok(attempts)
+}
+
+// ==================== Scheduler Commands ====================
+
+/// Fits the FSRS weights to the review history and, when the fit beats the
+/// current weights, saves them and swaps them into the running scheduler.
+#[tauri::command]
+pub async fn optimize_fsrs_parameters(state: State<'_, AppState>) -> Result<FsrsOptimizationReport, String> {
+    let attempts = state.db.get_all_attempts().map_err(|e| e.to_string())?;
+    let current = state.fsrs.read().unwrap().params().clone();
+
+    let (params, report) = fsrs_optimizer::optimize(&attempts, &current).map_err(|e| e.to_string())?;
+
+    if report.applied {
+        state.db.save_fsrs_parameters(&params).map_err(|e| e.to_string())?;
+        *state.fsrs.write().unwrap() = FSRSScheduler::new(params);
+    }
+
+    Ok(report)
+}
+
+/// Refits item difficulty (and, for 2PL, discrimination) to the attempt
+/// history and writes the results back to the items. Defaults to 2PL.
+#[tauri::command]
+pub async fn calibrate_items(state: State<'_, AppState>, model: Option<IrtModel>) -> Result<IrtCalibrationReport, String> {
+    let attempts = state.db.get_all_attempts().map_err(|e| e.to_string())?;
+    let items = state.db.get_all_items().map_err(|e| e.to_string())?;
+
+    let (calibrations, report) =
+        irt::calibrate(&attempts, &items, model.unwrap_or(IrtModel::TwoPl)).map_err(|e| e.to_string())?;
+    state.db.update_item_calibration(&calibrations).map_err(|e| e.to_string())?;
+
+    Ok(report)
 }
 
 // ==================== Session Commands ====================
 
+/// Exams draw `total_items` items up front; see `get_session_items`. For
+/// diagnostic and focused sessions `total_items` is an upper bound, with 0
+/// for none.
 #[tauri::command]
 pub fn create_session(state: State<AppState>, session_type: SessionType, total_items: i32) -> Result<Session, String> {
+    if let SessionType::Exam { time_limit_ms } = session_type {
+        return exam::start(&state.db, time_limit_ms, total_items).map_err(|e| e.to_string());
+    }
+    if matches!(session_type, SessionType::Focused { .. }) {
+        return focused::start(&state.db, session_type, total_items).map_err(|e| e.to_string());
+    }
     let session = Session::new(session_type, total_items);
     state.db.create_session(&session).map_err(|e| e.to_string())?;
     Ok(session)
 }
 
+/// Items fixed for the session. Explanations and answers are withheld while an
+/// exam is open.
 #[tauri::command]
-pub fn complete_session(
-    state: State<AppState>,
-    session_id: String,
-    completed_items: i32,
-    accuracy: f64,
-    average_confidence: f64,
-) -> Result<(), String> {
-    let mut session = state.db.get_session(&session_id).map_err(|e| e.to_string())?
+pub fn get_session_items(state: State<AppState>, session_id: String) -> Result<Vec<Item>, String> {
+    let session = state.db.get_session(&session_id).map_err(|e| e.to_string())?
         .ok_or("Session not found")?;
-    
-    session.completed_at = Some(Utc::now());
-    session.completed_items = completed_items;
-    session.accuracy = accuracy;
-    session.average_confidence = average_confidence;
-    
-    state.db.update_session(&session).map_err(|e| e.to_string())
+    let items = state.db.get_session_items(&session_id).map_err(|e| e.to_string())?;
+    let open_exam = session.deadline().is_some() && session.completed_at.is_none();
+    Ok(items
+        .iter()
+        .map(formula::render)
+        .map(|item| if open_exam { exam::withhold_answers(item) } else { item })
+        .collect())
+}
+
+/// Next item of an adaptive diagnostic; `None` once it has finished, which
+/// also completes the session.
+#[tauri::command]
+pub fn get_next_diagnostic_item(state: State<AppState>, session_id: String) -> Result<Option<Item>, String> {
+    let session = state.db.get_session(&session_id).map_err(|e| e.to_string())?
+        .ok_or("Session not found")?;
+    if !matches!(session.session_type, SessionType::Diagnostic) {
+        return Err("Session is not a diagnostic".to_string());
+    }
+    let item = diagnostic::next_item(&state.db, &session, Utc::now()).map_err(|e| e.to_string())?;
+    Ok(item.map(|item| formula::render(&item)))
+}
+
+/// Next item of a focused session; `None` once a target is reached or the
+/// pool runs out, which also completes the session.
+#[tauri::command]
+pub fn get_next_focused_item(state: State<AppState>, session_id: String) -> Result<Option<Item>, String> {
+    let session = state.db.get_session(&session_id).map_err(|e| e.to_string())?
+        .ok_or("Session not found")?;
+    let mapping = state.db.get_confidence_mapping().map_err(|e| e.to_string())?.unwrap_or_default();
+    let item = focused::next_item(&state.db, &state.sir, &mapping, &session, Utc::now()).map_err(|e| e.to_string())?;
+    Ok(item.map(|item| formula::render(&item)))
+}
+
+#[tauri::command]
+pub fn get_concept_abilities(state: State<AppState>) -> Result<Vec<ConceptAbility>, String> {
+    state.db.get_concept_abilities().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_exam_results(state: State<AppState>, session_id: String) -> Result<ExamResults, String> {
+    exam::results(&state.db, &session_id, Utc::now()).map_err(|e| e.to_string())
+}
+
+/// Stats are aggregated from the attempts recorded against the session.
+#[tauri::command]
+pub fn complete_session(state: State<AppState>, session_id: String) -> Result<Session, String> {
+    let session = state.db.get_session(&session_id).map_err(|e| e.to_string())?
+        .ok_or("Session not found")?;
+    session_report::complete(&state.db, &session, Utc::now()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_session_report(state: State<AppState>, session_id: String) -> Result<SessionReport, String> {
+    let mapping = state.db.get_confidence_mapping().map_err(|e| e.to_string())?.unwrap_or_default();
+    session_report::report(&state.db, &session_id, &mapping).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -160,22 +457,20 @@ pub fn get_all_sessions(state: State<AppState>) -> Result<Vec<Session>, String>
 
 #[tauri::command]
 pub fn get_concept_mastery(state: State<AppState>) -> Result<Vec<ConceptMastery>, String> {
+    compute_concept_mastery(&state)
+}
+
+fn compute_concept_mastery(state: &AppState) -> Result<Vec<ConceptMastery>, String> {
     let concepts = state.db.get_all_concepts().map_err(|e| e.to_string())?;
-    let items = state.db.get_all_items().map_err(|e| e.to_string())?;
+    let mut attempts_by_concept = state.db.get_attempts_by_concept().map_err(|e| e.to_string())?;
+    let mapping = state.db.get_confidence_mapping().map_err(|e| e.to_string())?.unwrap_or_default();
     
     let mut mastery_list = Vec::new();
     
     for concept in concepts {
-        let concept_items: Vec<&Item> = items.iter().filter(|i| i.concept_ids.contains(&concept.id)).collect();
-        
-        let mut all_attempts = Vec::new();
-        for item in &concept_items {
-            if let Ok(attempts) = state.db.get_attempts_by_item(&item.id) {
-                all_attempts.extend(attempts);
-            }
-        }
+        let all_attempts = attempts_by_concept.remove(&concept.id).unwrap_or_default();
         
-        let mastery_score = state.sir.calculate_mastery(&all_attempts);
+        let mastery_score = state.sir.calculate_mastery(&all_attempts, &mapping);
         
         let attempts_count = all_attempts.len() as i32;
         let correct_count = all_attempts.iter().filter(|a| a.is_correct).count() as i32;
@@ -186,7 +481,7 @@ pub fn get_concept_mastery(state: State<AppState>) -> Result<Vec<ConceptMastery>
             0.0
         };
         
-        let brier_score = state.sir.calculate_brier_score(&all_attempts);
+        let brier_score = state.sir.calculate_brier_score(&all_attempts, &mapping);
         let last_attempted = all_attempts.first().map(|a| a.attempted_at);
         
         let avg_stability = if !all_attempts.is_empty() {
@@ -195,7 +490,7 @@ pub fn get_concept_mastery(state: State<AppState>) -> Result<Vec<ConceptMastery>
             0.0
         };
         
-        let trend = "stable".to_string();
+        let (trend, trend_slope) = state.sir.calculate_trend(&all_attempts, &state.fsrs.read().unwrap(), Utc::now());
         
         mastery_list.push(ConceptMastery {
             concept_id: concept.id,
@@ -207,7 +502,9 @@ pub fn get_concept_mastery(state: State<AppState>) -> Result<Vec<ConceptMastery>
             brier_score,
             last_attempted,
             stability: avg_stability,
-            trend,
+            trend: trend.to_string(),
+            trend_slope,
+            trend_window_days: TREND_WINDOW_DAYS,
         });
     }
     
@@ -215,65 +512,118 @@ pub fn get_concept_mastery(state: State<AppState>) -> Result<Vec<ConceptMastery>
 }
 
 #[tauri::command]
-pub fn get_daily_plan(state: State<AppState>) -> Result<DailyPlan, String> {
+pub fn get_root_cause_analysis(
+    state: State<AppState>,
+    concept_id: String,
+    threshold: Option<f64>,
+) -> Result<RootCauseReport, String> {
+    let threshold = threshold.unwrap_or(concept_graph::WEAK_THRESHOLD);
+    let mastery: HashMap<String, ConceptMastery> = compute_concept_mastery(&state)?
+        .into_iter()
+        .map(|m| (m.concept_id.clone(), m))
+        .collect();
+    let target = mastery.get(&concept_id).ok_or("Concept not found")?;
+    let edges = state.db.get_concept_edges().map_err(|e| e.to_string())?;
+
+    Ok(RootCauseReport {
+        concept_id: target.concept_id.clone(),
+        concept_name: target.concept_name.clone(),
+        mastery_score: target.mastery_score,
+        threshold,
+        is_weak: target.mastery_score < threshold,
+        root_causes: concept_graph::root_causes(&concept_id, &edges, &mastery, threshold),
+    })
+}
+
+#[tauri::command]
+pub fn get_error_analysis(state: State<AppState>, filter: AttemptFilter) -> Result<Vec<ErrorAnalysis>, String> {
+    let attempts = state.db.get_attempts_filtered(&filter).map_err(|e| e.to_string())?;
+    let items: HashMap<String, Item> = state
+        .db
+        .get_all_items()
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|item| (item.id.clone(), item))
+        .collect();
+    Ok(error_analysis::analyze(&attempts, &items))
+}
+
+/// Option statistics for one MCQ item, or for every MCQ item when `item_id`
+/// is omitted.
+#[tauri::command]
+pub fn get_distractor_analysis(state: State<AppState>, item_id: Option<String>) -> Result<Vec<DistractorReport>, String> {
+    let attempts = state.db.get_attempts_filtered(&AttemptFilter::default()).map_err(|e| e.to_string())?;
     let items = state.db.get_all_items().map_err(|e| e.to_string())?;
-    let concepts = state.db.get_all_concepts().map_err(|e| e.to_string())?;
-    
-    let mut reviews = Vec::new();
-    let mut diagnostics = Vec::new();
-    
-    for item in &items {
-        let attempts = state.db.get_attempts_by_item(&item.id).map_err(|e| e.to_string())?;
-        
-        if attempts.is_empty() {
-            if diagnostics.len() < 3 {
-                diagnostics.push(PlannedItem {
-                    item_id: item.id.clone(),
-                    concept_id: item.concept_ids.first().unwrap_or(&String::new()).clone(),
-                    reason: "new_concept".to_string(),
-                    priority: 2,
-                });
-            }
-        } else {
-            let last_attempt = &attempts[0];
-            if last_attempt.scheduled_days <= last_attempt.elapsed_days && reviews.len() < 12 {
-                reviews.push(PlannedItem {
-                    item_id: item.id.clone(),
-                    concept_id: item.concept_ids.first().unwrap_or(&String::new()).clone(),
-                    reason: "due_for_review".to_string(),
-                    priority: if last_attempt.stability < 7.0 { 3 } else { 1 },
-                });
-            }
-        }
+    let mapping = state.db.get_confidence_mapping().map_err(|e| e.to_string())?.unwrap_or_default();
+    let mut reports = distractor_analysis::analyze(&attempts, &items, &state.sir, &mapping);
+    if let Some(item_id) = item_id {
+        reports.retain(|r| r.item_id == item_id);
     }
-    
-    reviews.sort_by(|a, b| b.priority.cmp(&a.priority));
-    reviews.truncate(12);
-    diagnostics.truncate(3);
-    
-    let total_items = (reviews.len() + diagnostics.len()) as i32;
-    let estimated_time_min = total_items * 2;
-    
-    let attempted_concepts: std::collections::HashSet<_> = items
-        .iter()
-        .filter(|i| state.db.get_attempts_by_item(&i.id).map(|a| !a.is_empty()).unwrap_or(false))
-        .flat_map(|i| i.concept_ids.clone())
+    Ok(reports)
+}
+
+/// Uses the saved confidence mapping, or the default when none was saved.
+#[tauri::command]
+pub fn get_calibration(state: State<AppState>, filter: AttemptFilter) -> Result<CalibrationAnalysis, String> {
+    let mapping = state.db.get_confidence_mapping().map_err(|e| e.to_string())?.unwrap_or_default();
+    let attempts = state.db.get_attempts_filtered(&filter).map_err(|e| e.to_string())?;
+    let items: HashMap<String, Item> = state
+        .db
+        .get_all_items()
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|item| (item.id.clone(), item))
         .collect();
-    
-    let coverage_percent = if !concepts.is_empty() {
-        (attempted_concepts.len() as f64 / concepts.len() as f64) * 100.0
-    } else {
-        0.0
+    let concepts: HashMap<String, Concept> = state
+        .db
+        .get_all_concepts()
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|concept| (concept.id.clone(), concept))
+        .collect();
+    Ok(calibration::analyze(&attempts, &items, &concepts, &mapping))
+}
+
+#[tauri::command]
+pub fn get_confidence_mapping(state: State<AppState>) -> Result<ConfidenceMapping, String> {
+    Ok(state.db.get_confidence_mapping().map_err(|e| e.to_string())?.unwrap_or_default())
+}
+
+#[tauri::command]
+pub fn set_confidence_mapping(state: State<AppState>, mapping: ConfidenceMapping) -> Result<(), String> {
+    calibration::validate_mapping(&mapping).map_err(|e| e.to_string())?;
+    state.db.save_confidence_mapping(&mapping).map_err(|e| e.to_string())
+}
+
+/// Plans `budget_minutes` of study, 30 when not given.
+#[tauri::command]
+pub fn get_daily_plan(state: State<AppState>, budget_minutes: Option<i32>) -> Result<DailyPlan, String> {
+    let items = state.db.get_all_items().map_err(|e| e.to_string())?;
+    let concepts: HashMap<String, Concept> = state
+        .db
+        .get_all_concepts()
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|c| (c.id.clone(), c))
+        .collect();
+    let item_states: HashMap<String, ItemState> = state
+        .db
+        .get_all_item_states()
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|s| (s.item_id.clone(), s))
+        .collect();
+    let average_time_ms = state.db.get_average_time_by_item().map_err(|e| e.to_string())?;
+
+    let input = planner::PlanInput {
+        items: &items,
+        states: &item_states,
+        concepts: &concepts,
+        average_time_ms: &average_time_ms,
     };
-    
-    Ok(DailyPlan {
-        date: Utc::now(),
-        reviews,
-        diagnostics,
-        total_items,
-        estimated_time_min,
-        coverage_percent,
-    })
+    let budget = budget_minutes.unwrap_or(planner::DEFAULT_BUDGET_MIN);
+    let fsrs = state.fsrs.read().unwrap();
+    Ok(planner::plan(&input, &fsrs, &state.sir, budget, Utc::now()))
 }
 
 #[tauri::command]
@@ -318,82 +668,115 @@ pub fn import_concepts_from_csv(state: State<AppState>, csv_content: String) ->
     Ok(concepts)
 }
 
+/// With `dry_run`, or when any row is invalid, nothing is written and the
+/// report lists the per-row errors.
+#[tauri::command]
+pub fn import_items_from_csv(
+    state: State<AppState>,
+    content: String,
+    mapping: ItemColumnMapping,
+    default_domain: String,
+    dry_run: bool,
+) -> Result<ItemImportReport, String> {
+    item_import::import_items(&state.db, &content, &mapping, &default_domain, dry_run).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn import_anki_deck(state: State<'_, AppState>, path: String) -> Result<AnkiImportReport, String> {
+    let fsrs = state.fsrs.read().unwrap();
+    anki::import_apkg(&state.db, &fsrs, &state.sir, Path::new(&path)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn export_anki_deck(
+    state: State<'_, AppState>,
+    destination: String,
+    concept_ids: Vec<String>,
+    item_ids: Vec<String>,
+    include_history: bool,
+) -> Result<AnkiExportReport, String> {
+    anki::export_apkg(&state.db, Path::new(&destination), &concept_ids, &item_ids, include_history)
+        .map_err(|e| e.to_string())
+}
+
 // ==================== Quick Learn Commands ====================
 
 #[tauri::command]
 pub fn get_next_review_item(state: State<AppState>) -> Result<Option<Item>, String> {
     let items = state.db.get_all_items().map_err(|e| e.to_string())?;
+    let mut item_states: HashMap<String, ItemState> = state
+        .db
+        .get_all_item_states()
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|s| (s.item_id.clone(), s))
+        .collect();
+    let now = Utc::now();
+    // Items drawn for a running exam are answered there
+    let in_exam = exam::open_item_ids(&state.db, now).map_err(|e| e.to_string())?;
     
     // Prioritize items for SIR-based review
     let mut due_items = Vec::new();
     let mut new_items = Vec::new();
     
-    for item in &items {
-        let attempts = state.db.get_attempts_by_item(&item.id).map_err(|e| e.to_string())?;
-        
-        if attempts.is_empty() {
-            new_items.push(item.clone());
-        } else if let Some(last_attempt) = attempts.first() {
-            // Use SIR scheduler to check if due
-            if state.sir.is_due(last_attempt) {
-                due_items.push((item.clone(), last_attempt.clone()));
+    for item in items.into_iter().filter(|item| !in_exam.contains(&item.id)) {
+        match item_states.remove(&item.id) {
+            None => new_items.push(item),
+            Some(item_state) if state.sir.is_due(item_state.next_review_date, now) => {
+                due_items.push((item, item_state));
             }
+            Some(_) => {}
         }
     }
     
     // Prioritize due items in early SIR phases, then new items
-    due_items.sort_by(|a, b| {
+    due_items.sort_by_key(|(_, item_state)| match item_state.sir_phase {
         // Earlier phases get higher priority
-        let phase_order_a = match a.1.sir_phase {
-            SirPhase::Encoding => 0,
-            SirPhase::ShortTermRetrieval => 1,
-            SirPhase::InterleavedRetrieval => 2,
-            SirPhase::MediumSpacing => 3,
-            SirPhase::IntegrationTransfer => 4,
-        };
-        let phase_order_b = match b.1.sir_phase {
-            SirPhase::Encoding => 0,
-            SirPhase::ShortTermRetrieval => 1,
-            SirPhase::InterleavedRetrieval => 2,
-            SirPhase::MediumSpacing => 3,
-            SirPhase::IntegrationTransfer => 4,
-        };
-        phase_order_a.cmp(&phase_order_b)
+        SirPhase::Encoding => 0,
+        SirPhase::ShortTermRetrieval => 1,
+        SirPhase::InterleavedRetrieval => 2,
+        SirPhase::MediumSpacing => 3,
+        SirPhase::IntegrationTransfer => 4,
     });
     
     // Return first due item, or first new item
-    Ok(due_items.into_iter().next().map(|(item, _)| item).or_else(|| new_items.into_iter().next()))
+    if let Some((item, _)) = due_items.into_iter().next() {
+        return Ok(Some(formula::render(&item)));
+    }
+    Ok(new_items.into_iter().next().map(|item| formula::render(&item)))
 }
 
 #[tauri::command]
 pub fn get_item_count(state: State<AppState>) -> Result<usize, String> {
-    let items = state.db.get_all_items().map_err(|e| e.to_string())?;
-    Ok(items.len())
+    state.db.count_items().map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 pub fn get_due_count(state: State<AppState>) -> Result<usize, String> {
-    let items = state.db.get_all_items().map_err(|e| e.to_string())?;
-    let mut due_count = 0;
-    
-    for item in items {
-        let attempts = state.db.get_attempts_by_item(&item.id).map_err(|e| e.to_string())?;
-        
-        if attempts.is_empty() {
-            due_count += 1;
-        } else if let Some(last_attempt) = attempts.first() {
-            // Use SIR scheduler to check if due
-            if state.sir.is_due(last_attempt) {
-                due_count += 1;
-            }
-        }
-    }
-    
-    Ok(due_count)
+    state.db.count_due_items(Utc::now()).map_err(|e| e.to_string())
 }
 
 // ==================== Database Management ====================
 
+#[tauri::command]
+pub fn backup_database(state: State<AppState>, destination: String) -> Result<(), String> {
+    state.db.backup_to(Path::new(&destination)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn export_collection(state: State<AppState>, destination: String) -> Result<ArchiveSummary, String> {
+    archive::export(&state.db, Path::new(&destination)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn restore_collection(
+    state: State<AppState>,
+    source: String,
+    mode: RestoreMode,
+) -> Result<ArchiveSummary, String> {
+    archive::restore(&state.db, Path::new(&source), mode).map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub fn clear_all_data(state: State<AppState>) -> Result<(), String> {
     state.db.clear_all().map_err(|e| e.to_string())
@@ -0,0 +1,104 @@
+use crate::models::{ConceptEdge, ConceptMastery, ConceptRelation, RootCause};
+use std::collections::{HashMap, HashSet};
+
+/// Mastery (0-100) below which a concept counts as a gap.
+pub const WEAK_THRESHOLD: f64 = 60.0;
+
+/// Returns the concept ids of the cycle that adding `from -> to` would close,
+/// starting and ending at `from`. Prerequisite and part-of edges must each form
+/// a DAG; related edges never form cycles.
+pub fn find_cycle(edges: &[ConceptEdge], from: &str, to: &str, relation: ConceptRelation) -> Option<Vec<String>> {
+    if relation == ConceptRelation::Related {
+        return None;
+    }
+    if from == to {
+        return Some(vec![from.to_string(), to.to_string()]);
+    }
+
+    let mut next: HashMap<&str, Vec<&str>> = HashMap::new();
+    for edge in edges.iter().filter(|e| e.relation == relation) {
+        next.entry(edge.from_concept_id.as_str()).or_default().push(edge.to_concept_id.as_str());
+    }
+
+    // Depth-first search for a path to -> ... -> from
+    let mut stack: Vec<Vec<&str>> = vec![vec![to]];
+    let mut visited: HashSet<&str> = HashSet::new();
+    while let Some(path) = stack.pop() {
+        let current = *path.last().unwrap();
+        if current == from {
+            let mut cycle = vec![from.to_string()];
+            cycle.extend(path.iter().map(|id| id.to_string()));
+            return Some(cycle);
+        }
+        if !visited.insert(current) {
+            continue;
+        }
+        for successor in next.get(current).into_iter().flatten() {
+            let mut extended = path.clone();
+            extended.push(successor);
+            stack.push(extended);
+        }
+    }
+    None
+}
+
+/// Walks back from `concept_id` through its prerequisites and components.
+/// A weak concept whose own prerequisites are all above `threshold` is a root
+/// cause; a weak concept with weak prerequisites is only on the way to one.
+/// Only attempted concepts count as weak: a prerequisite not yet studied has
+/// no evidence against it. The walk stops at concepts that are not weak.
+/// Results are weakest first.
+pub fn root_causes(
+    concept_id: &str,
+    edges: &[ConceptEdge],
+    mastery: &HashMap<String, ConceptMastery>,
+    threshold: f64,
+) -> Vec<RootCause> {
+    let mut underneath: HashMap<&str, Vec<&str>> = HashMap::new();
+    for edge in edges.iter().filter(|e| e.relation != ConceptRelation::Related) {
+        underneath.entry(edge.to_concept_id.as_str()).or_default().push(edge.from_concept_id.as_str());
+    }
+    let is_weak = |id: &str| mastery.get(id).is_some_and(|m| m.attempts > 0 && m.mastery_score < threshold);
+
+    let mut causes: Vec<RootCause> = Vec::new();
+    let mut seen: HashSet<&str> = HashSet::from([concept_id]);
+    let mut stack: Vec<Vec<&str>> = vec![vec![concept_id]];
+
+    while let Some(path) = stack.pop() {
+        let current = *path.last().unwrap();
+        let weak_below: Vec<&str> = underneath
+            .get(current)
+            .into_iter()
+            .flatten()
+            .copied()
+            .filter(|id| is_weak(id))
+            .collect();
+
+        if weak_below.is_empty() {
+            if path.len() > 1 {
+                if let Some(m) = mastery.get(current) {
+                    causes.push(RootCause {
+                        concept_id: m.concept_id.clone(),
+                        concept_name: m.concept_name.clone(),
+                        mastery_score: m.mastery_score,
+                        attempts: m.attempts,
+                        depth: path.len() - 1,
+                        path: path.iter().map(|id| id.to_string()).collect(),
+                    });
+                }
+            }
+            continue;
+        }
+
+        for id in weak_below {
+            if seen.insert(id) {
+                let mut extended = path.clone();
+                extended.push(id);
+                stack.push(extended);
+            }
+        }
+    }
+
+    causes.sort_by(|a, b| a.mastery_score.total_cmp(&b.mastery_score).then(a.depth.cmp(&b.depth)));
+    causes
+}
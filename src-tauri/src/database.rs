@@ -1,8 +1,12 @@
 use anyhow::{Context, Result};
 use rusqlite::{params, Connection, OptionalExtension};
 use rusqlite::types::ValueRef;
-use std::path::PathBuf;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use crate::fsrs::FSRSParameters;
+use crate::migrations;
 use crate::models::*;
 
 pub struct Database {
@@ -10,12 +14,43 @@ pub struct Database {
 }
 
 impl Database {
+    /// Opens the database and applies pending migrations. An existing file is
+    /// first copied to `<name>.v<old version>-<timestamp>.bak` next to it.
     pub fn new(db_path: PathBuf) -> Result<Self> {
-        let conn = Connection::open(db_path).context("Failed to open database")?;
+        let mut conn = Connection::open(&db_path).context("Failed to open database")?;
+        conn.execute("PRAGMA foreign_keys = ON", [])?;
+
+        let version = migrations::current_version(&conn)?;
+        if version < migrations::SCHEMA_VERSION && migrations::has_user_tables(&conn)? {
+            let file_name = db_path.file_name().and_then(|n| n.to_str()).unwrap_or("gapfinder.db");
+            let backup_path = db_path.with_file_name(format!(
+                "{}.v{}-{}.bak",
+                file_name,
+                version,
+                Utc::now().format("%Y%m%d%H%M%S")
+            ));
+            Self::vacuum_into(&conn, &backup_path).context("Failed to back up database before upgrading")?;
+        }
+        migrations::migrate(&mut conn)?;
+
         let conn = Arc::new(Mutex::new(conn));
-        let db = Self { conn };
-        db.init_schema()?;
-        Ok(db)
+        Ok(Self { conn })
+    }
+
+    fn vacuum_into(conn: &Connection, path: &Path) -> Result<()> {
+        conn.execute("VACUUM INTO ?1", params![path.to_string_lossy()])?;
+        Ok(())
+    }
+
+    pub fn schema_version(&self) -> Result<i32> {
+        let conn = self.conn.lock().unwrap();
+        migrations::current_version(&conn)
+    }
+
+    /// Writes a consistent copy of the whole database to `path`.
+    pub fn backup_to(&self, path: &Path) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        Self::vacuum_into(&conn, path)
     }
 
     fn read_f64(row: &rusqlite::Row<'_>, idx: usize) -> rusqlite::Result<f64> {
@@ -34,127 +69,6 @@ impl Database {
         }
     }
 
-    fn init_schema(&self) -> Result<()> {
-        let conn = self.conn.lock().unwrap();
-        
-        // Enable foreign keys (optional but good practice)
-        conn.execute("PRAGMA foreign_keys = ON", [])?;
-        
-        // Learning materials table
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS learning_materials (
-                id TEXT PRIMARY KEY,
-                content TEXT NOT NULL,
-                domain TEXT NOT NULL,
-                encoding_date TEXT NOT NULL,
-                created_at TEXT NOT NULL
-            )",
-            [],
-        )?;
-        
-        // Updated concepts table (remove learning_objectives, add learning_material_id)
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS concepts (
-                id TEXT PRIMARY KEY,
-                name TEXT NOT NULL,
-                domain TEXT NOT NULL,
-                subdomain TEXT,
-                description TEXT,
-                tags TEXT,
-                learning_material_id TEXT,
-                created_at TEXT NOT NULL,
-                updated_at TEXT NOT NULL
-            )",
-            [],
-        )?;
-        
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS items (
-                id TEXT PRIMARY KEY,
-                stem TEXT NOT NULL,
-                item_type TEXT NOT NULL,
-                concept_ids TEXT NOT NULL,
-                difficulty INTEGER NOT NULL,
-                source TEXT,
-                explanation TEXT NOT NULL,
-                created_at TEXT NOT NULL,
-                updated_at TEXT NOT NULL
-            )",
-            [],
-        )?;
-        
-        // Updated attempts table with SIR fields
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS attempts (
-                id TEXT PRIMARY KEY,
-                item_id TEXT NOT NULL,
-                session_id TEXT,
-                user_answer TEXT NOT NULL,
-                is_correct BOOLEAN NOT NULL,
-                confidence INTEGER NOT NULL,
-                time_spent_ms INTEGER NOT NULL,
-                attempted_at TEXT NOT NULL,
-                sir_phase TEXT NOT NULL,
-                next_review_date TEXT NOT NULL,
-                metacognitive TEXT,
-                stability REAL NOT NULL,
-                difficulty REAL NOT NULL,
-                elapsed_days INTEGER NOT NULL,
-                scheduled_days INTEGER NOT NULL,
-                review_state TEXT NOT NULL
-            )",
-            [],
-        )?;
-        
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS sessions (
-                id TEXT PRIMARY KEY,
-                session_type TEXT NOT NULL,
-                started_at TEXT NOT NULL,
-                completed_at TEXT,
-                total_items INTEGER NOT NULL,
-                completed_items INTEGER NOT NULL,
-                accuracy REAL NOT NULL,
-                average_confidence REAL NOT NULL
-            )",
-            [],
-        )?;
-        
-        // Migration: Add new columns to existing tables if they don't exist
-        // SQLite doesn't have "ADD COLUMN IF NOT EXISTS", so we ignore errors if columns exist
-        let _ = conn.execute(
-            "ALTER TABLE concepts ADD COLUMN learning_material_id TEXT",
-            [],
-        );
-        
-        let _ = conn.execute(
-            "ALTER TABLE attempts ADD COLUMN sir_phase TEXT NOT NULL DEFAULT 'Encoding'",
-            [],
-        );
-        
-        let _ = conn.execute(
-            "ALTER TABLE attempts ADD COLUMN next_review_date TEXT NOT NULL DEFAULT '1970-01-01T00:00:00Z'",
-            [],
-        );
-        
-        let _ = conn.execute(
-            "ALTER TABLE attempts ADD COLUMN metacognitive TEXT",
-            [],
-        );
-        
-        let _ = conn.execute(
-            "ALTER TABLE sessions ADD COLUMN accuracy REAL NOT NULL DEFAULT 0.0",
-            [],
-        );
-        
-        // Create indexes AFTER adding columns
-        conn.execute("CREATE INDEX IF NOT EXISTS idx_attempts_item_id ON attempts(item_id)", [])?;
-        conn.execute("CREATE INDEX IF NOT EXISTS idx_attempts_session_id ON attempts(session_id)", [])?;
-        conn.execute("CREATE INDEX IF NOT EXISTS idx_attempts_next_review ON attempts(next_review_date)", [])?;
-        
-        Ok(())
-    }
-
     pub fn create_learning_material(&self, material: &LearningMaterial) -> Result<()> {
         let conn = self.conn.lock().unwrap();
         conn.execute(
@@ -170,23 +84,82 @@ impl Database {
         Ok(())
     }
 
+    const MATERIAL_COLUMNS: &'static str = "id, content, domain, encoding_date, created_at";
+
+    fn row_to_material(row: &rusqlite::Row<'_>) -> rusqlite::Result<LearningMaterial> {
+        Ok(LearningMaterial {
+            id: row.get(0)?,
+            content: row.get(1)?,
+            domain: row.get(2)?,
+            encoding_date: row.get::<_, String>(3)?.parse().unwrap(),
+            created_at: row.get::<_, String>(4)?.parse().unwrap(),
+        })
+    }
+
     pub fn get_learning_material(&self, id: &str) -> Result<Option<LearningMaterial>> {
         let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare("SELECT * FROM learning_materials WHERE id = ?1")?;
-        let material = stmt
-            .query_row([id], |row| {
-                Ok(LearningMaterial {
-                    id: row.get(0)?,
-                    content: row.get(1)?,
-                    domain: row.get(2)?,
-                    encoding_date: row.get::<_, String>(3)?.parse().unwrap(),
-                    created_at: row.get::<_, String>(4)?.parse().unwrap(),
-                })
-            })
-            .optional()?;
+        let mut stmt = conn.prepare(&format!("SELECT {} FROM learning_materials WHERE id = ?1", Self::MATERIAL_COLUMNS))?;
+        let material = stmt.query_row([id], Self::row_to_material).optional()?;
         Ok(material)
     }
 
+    pub fn get_all_learning_materials(&self) -> Result<Vec<LearningMaterial>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM learning_materials ORDER BY created_at",
+            Self::MATERIAL_COLUMNS
+        ))?;
+        let materials = stmt.query_map([], Self::row_to_material)?.collect::<Result<Vec<_>, _>>()?;
+        Ok(materials)
+    }
+
+    pub fn get_learning_materials_by_domain(&self, domain: &str) -> Result<Vec<LearningMaterial>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM learning_materials WHERE domain = ?1 ORDER BY created_at",
+            Self::MATERIAL_COLUMNS
+        ))?;
+        let materials = stmt.query_map([domain], Self::row_to_material)?.collect::<Result<Vec<_>, _>>()?;
+        Ok(materials)
+    }
+
+    /// Case-insensitive substring match on content or domain; `%` and `_` in
+    /// `query` match literally.
+    pub fn search_learning_materials(&self, query: &str) -> Result<Vec<LearningMaterial>> {
+        let pattern = format!(
+            "%{}%",
+            query.trim().replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+        );
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM learning_materials
+             WHERE content LIKE ?1 ESCAPE '\\' OR domain LIKE ?1 ESCAPE '\\'
+             ORDER BY created_at",
+            Self::MATERIAL_COLUMNS
+        ))?;
+        let materials = stmt.query_map([pattern], Self::row_to_material)?.collect::<Result<Vec<_>, _>>()?;
+        Ok(materials)
+    }
+
+    pub fn update_learning_material(&self, material: &LearningMaterial) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE learning_materials SET content=?2, domain=?3, encoding_date=?4 WHERE id=?1",
+            params![material.id, material.content, material.domain, material.encoding_date.to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    /// Concepts pointing at the material are kept but detached from it.
+    pub fn delete_learning_material(&self, id: &str) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute("UPDATE concepts SET learning_material_id = NULL WHERE learning_material_id = ?1", params![id])?;
+        tx.execute("DELETE FROM learning_materials WHERE id = ?1", params![id])?;
+        tx.commit()?;
+        Ok(())
+    }
+
     pub fn create_concept(&self, concept: &Concept) -> Result<()> {
         let conn = self.conn.lock().unwrap();
         conn.execute(
@@ -207,24 +180,57 @@ impl Database {
         Ok(())
     }
 
+    const CONCEPT_COLUMNS: &'static str = "concepts.id, concepts.name, concepts.domain, concepts.subdomain, concepts.description,
+                    concepts.tags, concepts.learning_material_id, concepts.created_at, concepts.updated_at";
+
+    fn row_to_concept(row: &rusqlite::Row<'_>) -> rusqlite::Result<Concept> {
+        Ok(Concept {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            domain: row.get(2)?,
+            subdomain: row.get(3)?,
+            description: row.get(4)?,
+            tags: serde_json::from_str(&row.get::<_, String>(5)?).unwrap_or_default(),
+            learning_material_id: row.get(6)?,
+            created_at: row.get::<_, String>(7)?.parse().unwrap(),
+            updated_at: row.get::<_, String>(8)?.parse().unwrap(),
+        })
+    }
+
     pub fn get_all_concepts(&self) -> Result<Vec<Concept>> {
         let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare("SELECT * FROM concepts ORDER BY name")?;
-        let concepts = stmt
-            .query_map([], |row| {
-                Ok(Concept {
-                    id: row.get(0)?,
-                    name: row.get(1)?,
-                    domain: row.get(2)?,
-                    subdomain: row.get(3)?,
-                    description: row.get(4)?,
-                    tags: serde_json::from_str(&row.get::<_, String>(5)?).unwrap_or_default(),
-                    learning_material_id: row.get(6)?,
-                    created_at: row.get::<_, String>(7)?.parse().unwrap(),
-                    updated_at: row.get::<_, String>(8)?.parse().unwrap(),
-                })
-            })?
-            .collect::<Result<Vec<_>, _>>()?;
+        let mut stmt = conn.prepare(&format!("SELECT {} FROM concepts ORDER BY name", Self::CONCEPT_COLUMNS))?;
+        let concepts = stmt.query_map([], Self::row_to_concept)?.collect::<Result<Vec<_>, _>>()?;
+        Ok(concepts)
+    }
+
+    pub fn get_concept(&self, id: &str) -> Result<Option<Concept>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!("SELECT {} FROM concepts WHERE id = ?1", Self::CONCEPT_COLUMNS))?;
+        let concept = stmt.query_row([id], Self::row_to_concept).optional()?;
+        Ok(concept)
+    }
+
+    /// Concepts whose `learning_material_id` points at the material.
+    pub fn get_concepts_by_material(&self, material_id: &str) -> Result<Vec<Concept>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM concepts WHERE learning_material_id = ?1 ORDER BY name",
+            Self::CONCEPT_COLUMNS
+        ))?;
+        let concepts = stmt.query_map([material_id], Self::row_to_concept)?.collect::<Result<Vec<_>, _>>()?;
+        Ok(concepts)
+    }
+
+    /// Concepts linked to an item, in the order they were assigned.
+    pub fn get_concepts_by_item(&self, item_id: &str) -> Result<Vec<Concept>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM concepts JOIN item_concepts ic ON ic.concept_id = concepts.id
+             WHERE ic.item_id = ?1 ORDER BY ic.position",
+            Self::CONCEPT_COLUMNS
+        ))?;
+        let concepts = stmt.query_map([item_id], Self::row_to_concept)?.collect::<Result<Vec<_>, _>>()?;
         Ok(concepts)
     }
 
@@ -246,52 +252,170 @@ impl Database {
         Ok(())
     }
 
+    /// Also detaches the concept from every item via `ON DELETE CASCADE`.
     pub fn delete_concept(&self, id: &str) -> Result<()> {
         let conn = self.conn.lock().unwrap();
         conn.execute("DELETE FROM concepts WHERE id = ?1", params![id])?;
         Ok(())
     }
 
-    pub fn create_item(&self, item: &Item) -> Result<()> {
+    pub fn add_concept_edge(&self, edge: &ConceptEdge) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO concept_edges (from_concept_id, to_concept_id, relation, created_at) VALUES (?1, ?2, ?3, ?4)",
+            params![edge.from_concept_id, edge.to_concept_id, edge.relation.as_str(), edge.created_at.to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_concept_edge(&self, from_concept_id: &str, to_concept_id: &str, relation: ConceptRelation) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "DELETE FROM concept_edges WHERE from_concept_id = ?1 AND to_concept_id = ?2 AND relation = ?3",
+            params![from_concept_id, to_concept_id, relation.as_str()],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_concept_edges(&self) -> Result<Vec<ConceptEdge>> {
         let conn = self.conn.lock().unwrap();
-        conn.execute("INSERT INTO items VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
-            params![item.id, item.stem, serde_json::to_string(&item.item_type)?, serde_json::to_string(&item.concept_ids)?,
-                    item.difficulty, item.source, item.explanation, item.created_at.to_rfc3339(), item.updated_at.to_rfc3339()])?;
+        let mut stmt = conn.prepare("SELECT from_concept_id, to_concept_id, relation, created_at FROM concept_edges")?;
+        let edges = stmt
+            .query_map([], |row| {
+                Ok(ConceptEdge {
+                    from_concept_id: row.get(0)?,
+                    to_concept_id: row.get(1)?,
+                    relation: ConceptRelation::parse(&row.get::<_, String>(2)?).unwrap(),
+                    created_at: row.get::<_, String>(3)?.parse().unwrap(),
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(edges)
+    }
+
+    pub fn create_item(&self, item: &Item) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute("INSERT INTO items (id, stem, item_type, difficulty, source, explanation, created_at, updated_at, discrimination) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![item.id, item.stem, serde_json::to_string(&item.item_type)?,
+                    item.difficulty, item.source, item.explanation, item.created_at.to_rfc3339(), item.updated_at.to_rfc3339(),
+                    item.discrimination])?;
+        Self::link_concepts(&tx, &item.id, &item.concept_ids)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Replaces an item's concept links. Unknown concept ids fail the foreign key check.
+    fn link_concepts(conn: &Connection, item_id: &str, concept_ids: &[String]) -> Result<()> {
+        conn.execute("DELETE FROM item_concepts WHERE item_id = ?1", params![item_id])?;
+        let mut stmt = conn.prepare("INSERT OR IGNORE INTO item_concepts (item_id, concept_id, position) VALUES (?1, ?2, ?3)")?;
+        for (position, concept_id) in concept_ids.iter().enumerate() {
+            stmt.execute(params![item_id, concept_id, position as i64])
+                .with_context(|| format!("Concept {} does not exist", concept_id))?;
+        }
         Ok(())
     }
 
+    /// `concept_ids` is rebuilt from `item_concepts` as a JSON array so it keeps its column slot.
+    const ITEM_COLUMNS: &'static str = "id, stem, item_type,
+                    (SELECT json_group_array(concept_id) FROM
+                        (SELECT concept_id FROM item_concepts WHERE item_id = items.id ORDER BY position)),
+                    difficulty, source, explanation, created_at, updated_at, discrimination";
+
+    fn row_to_item(row: &rusqlite::Row<'_>) -> rusqlite::Result<Item> {
+        Ok(Item {
+            id: row.get(0)?, stem: row.get(1)?, item_type: serde_json::from_str(&row.get::<_, String>(2)?).unwrap(),
+            concept_ids: serde_json::from_str(&row.get::<_, String>(3)?).unwrap(),
+            difficulty: row.get(4)?, discrimination: Self::read_f64(row, 9)?, source: row.get(5)?, explanation: row.get(6)?,
+            created_at: row.get::<_, String>(7)?.parse().unwrap(), updated_at: row.get::<_, String>(8)?.parse().unwrap(),
+        })
+    }
+
+    pub fn count_items(&self) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        let count: i64 = conn.query_row("SELECT COUNT(*) FROM items", [], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+
     pub fn get_all_items(&self) -> Result<Vec<Item>> {
         let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare("SELECT * FROM items")?;
-        let items = stmt.query_map([], |row| {
-            Ok(Item {
-                id: row.get(0)?, stem: row.get(1)?, item_type: serde_json::from_str(&row.get::<_, String>(2)?).unwrap(),
-                concept_ids: serde_json::from_str(&row.get::<_, String>(3)?).unwrap(),
-                difficulty: row.get(4)?, source: row.get(5)?, explanation: row.get(6)?,
-                created_at: row.get::<_, String>(7)?.parse().unwrap(), updated_at: row.get::<_, String>(8)?.parse().unwrap(),
-            })
-        })?.collect::<Result<Vec<_>, _>>()?;
+        let mut stmt = conn.prepare(&format!("SELECT {} FROM items", Self::ITEM_COLUMNS))?;
+        let items = stmt.query_map([], Self::row_to_item)?.collect::<Result<Vec<_>, _>>()?;
         Ok(items)
     }
 
-    pub fn update_item(&self, item: &Item) -> Result<()> {
+    pub fn get_item(&self, id: &str) -> Result<Option<Item>> {
         let conn = self.conn.lock().unwrap();
-        conn.execute("UPDATE items SET stem=?2, item_type=?3, concept_ids=?4, difficulty=?5, source=?6, explanation=?7, updated_at=?8 WHERE id=?1",
-            params![item.id, item.stem, serde_json::to_string(&item.item_type)?, serde_json::to_string(&item.concept_ids)?,
-                    item.difficulty, item.source, item.explanation, item.updated_at.to_rfc3339()])?;
+        let mut stmt = conn.prepare(&format!("SELECT {} FROM items WHERE id = ?1", Self::ITEM_COLUMNS))?;
+        let item = stmt.query_row([id], Self::row_to_item).optional()?;
+        Ok(item)
+    }
+
+    /// Items linked to a concept, answered from the `item_concepts` index.
+    pub fn get_items_by_concept(&self, concept_id: &str) -> Result<Vec<Item>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM items WHERE id IN (SELECT item_id FROM item_concepts WHERE concept_id = ?1)",
+            Self::ITEM_COLUMNS
+        ))?;
+        let items = stmt.query_map([concept_id], Self::row_to_item)?.collect::<Result<Vec<_>, _>>()?;
+        Ok(items)
+    }
+
+    /// Items linked to any concept of the material.
+    pub fn get_items_by_material(&self, material_id: &str) -> Result<Vec<Item>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM items WHERE id IN (
+                SELECT ic.item_id FROM item_concepts ic JOIN concepts c ON c.id = ic.concept_id
+                WHERE c.learning_material_id = ?1
+             )",
+            Self::ITEM_COLUMNS
+        ))?;
+        let items = stmt.query_map([material_id], Self::row_to_item)?.collect::<Result<Vec<_>, _>>()?;
+        Ok(items)
+    }
+
+    pub fn update_item(&self, item: &Item) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute("UPDATE items SET stem=?2, item_type=?3, difficulty=?4, source=?5, explanation=?6, updated_at=?7, discrimination=?8 WHERE id=?1",
+            params![item.id, item.stem, serde_json::to_string(&item.item_type)?,
+                    item.difficulty, item.source, item.explanation, item.updated_at.to_rfc3339(), item.discrimination])?;
+        Self::link_concepts(&tx, &item.id, &item.concept_ids)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Writes fitted `(item id, difficulty, discrimination)` values in one transaction.
+    pub fn update_item_calibration(&self, calibrations: &[(String, i32, f64)]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        {
+            let mut stmt = tx.prepare("UPDATE items SET difficulty = ?2, discrimination = ?3 WHERE id = ?1")?;
+            for (item_id, difficulty, discrimination) in calibrations {
+                stmt.execute(params![item_id, difficulty, discrimination])?;
+            }
+        }
+        tx.commit()?;
         Ok(())
     }
 
+    /// Concept links and review state are removed by `ON DELETE CASCADE`.
     pub fn delete_item(&self, id: &str) -> Result<()> {
         let conn = self.conn.lock().unwrap();
         conn.execute("DELETE FROM items WHERE id = ?1", params![id])?;
         Ok(())
     }
 
+    /// Stores the attempt and folds it into the item's review state. An attempt
+    /// older than the state's latest one (an archive merge or Anki replay)
+    /// only adds to the counts.
     pub fn create_attempt(&self, attempt: &Attempt) -> Result<()> {
-        let conn = self.conn.lock().unwrap();
-        conn.execute(
-            "INSERT INTO attempts VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO attempts VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)",
             params![
                 attempt.id,
                 attempt.item_id,
@@ -308,42 +432,229 @@ impl Database {
                 attempt.difficulty,
                 attempt.elapsed_days,
                 attempt.scheduled_days,
-                serde_json::to_string(&attempt.review_state)?
+                serde_json::to_string(&attempt.review_state)?,
+                attempt.score,
+                serde_json::to_string(&attempt.feedback)?
+            ],
+        )?;
+
+        let is_lapse = !attempt.is_correct && attempt.review_state == ReviewState::Relearning;
+        let updated = tx.execute(
+            "INSERT INTO item_states VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, 1, ?10)
+             ON CONFLICT(item_id) DO UPDATE SET
+                last_attempt_id = excluded.last_attempt_id,
+                last_attempted_at = excluded.last_attempted_at,
+                stability = excluded.stability,
+                difficulty = excluded.difficulty,
+                review_state = excluded.review_state,
+                sir_phase = excluded.sir_phase,
+                scheduled_days = excluded.scheduled_days,
+                next_review_date = excluded.next_review_date,
+                reps = reps + 1,
+                lapses = lapses + excluded.lapses
+             WHERE excluded.last_attempted_at >= item_states.last_attempted_at",
+            params![
+                attempt.item_id,
+                attempt.id,
+                attempt.attempted_at.to_rfc3339(),
+                attempt.stability,
+                attempt.difficulty,
+                serde_json::to_string(&attempt.review_state)?,
+                serde_json::to_string(&attempt.sir_phase)?,
+                attempt.scheduled_days,
+                attempt.next_review_date.to_rfc3339(),
+                is_lapse as i32
             ],
         )?;
+        if updated == 0 {
+            tx.execute(
+                "UPDATE item_states SET reps = reps + 1, lapses = lapses + ?2 WHERE item_id = ?1",
+                params![attempt.item_id, is_lapse as i32],
+            )?;
+        }
+        tx.commit()?;
         Ok(())
     }
 
+    const ITEM_STATE_COLUMNS: &'static str = "item_id, last_attempt_id, last_attempted_at, stability, difficulty,
+                    review_state, sir_phase, scheduled_days, next_review_date, reps, lapses";
+
+    fn row_to_item_state(row: &rusqlite::Row<'_>) -> rusqlite::Result<ItemState> {
+        Ok(ItemState {
+            item_id: row.get(0)?,
+            last_attempt_id: row.get(1)?,
+            last_attempted_at: row.get::<_, String>(2)?.parse().unwrap(),
+            stability: Self::read_f64(row, 3)?,
+            difficulty: Self::read_f64(row, 4)?,
+            review_state: serde_json::from_str(&row.get::<_, String>(5)?).unwrap_or(ReviewState::New),
+            sir_phase: serde_json::from_str(&row.get::<_, String>(6)?).unwrap_or(SirPhase::Encoding),
+            scheduled_days: row.get(7)?,
+            next_review_date: row.get::<_, String>(8)?.parse().unwrap_or_else(|_| chrono::Utc::now()),
+            reps: row.get(9)?,
+            lapses: row.get(10)?,
+        })
+    }
+
+    pub fn get_item_state(&self, item_id: &str) -> Result<Option<ItemState>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM item_states WHERE item_id = ?1",
+            Self::ITEM_STATE_COLUMNS
+        ))?;
+        let state = stmt.query_row([item_id], Self::row_to_item_state).optional()?;
+        Ok(state)
+    }
+
+    /// Current state of every item that has been attempted.
+    pub fn get_all_item_states(&self) -> Result<Vec<ItemState>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!("SELECT {} FROM item_states", Self::ITEM_STATE_COLUMNS))?;
+        let states = stmt
+            .query_map([], Self::row_to_item_state)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(states)
+    }
+
+    /// Items never attempted plus items whose next review is at or before `now`.
+    pub fn count_due_items(&self, now: DateTime<Utc>) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        let count: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM items
+             LEFT JOIN item_states s ON s.item_id = items.id
+             WHERE s.item_id IS NULL OR julianday(s.next_review_date) <= julianday(?1)",
+            params![now.to_rfc3339()],
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+
+    const ATTEMPT_COLUMNS: &'static str = "id, item_id, session_id, user_answer, is_correct, confidence, time_spent_ms, attempted_at, 
+                    sir_phase, next_review_date, metacognitive, stability, difficulty, elapsed_days, scheduled_days, review_state,
+                    score, feedback";
+
+    fn row_to_attempt(row: &rusqlite::Row<'_>) -> rusqlite::Result<Attempt> {
+        let is_correct: bool = row.get(4)?;
+        Ok(Attempt {
+            id: row.get(0)?,
+            item_id: row.get(1)?,
+            session_id: row.get(2)?,
+            user_answer: row.get(3)?,
+            is_correct,
+            confidence: row.get(5)?,
+            time_spent_ms: row.get(6)?,
+            attempted_at: row.get::<_, String>(7)?.parse().unwrap(),
+            sir_phase: serde_json::from_str(&row.get::<_, String>(8)?).unwrap_or(SirPhase::Encoding),
+            next_review_date: row.get::<_, String>(9)?.parse().unwrap_or_else(|_| chrono::Utc::now()),
+            metacognitive: row
+                .get::<_, Option<String>>(10)?
+                .and_then(|s| serde_json::from_str(&s).ok()),
+            // Attempts recorded before server-side grading only have is_correct
+            score: row
+                .get::<_, Option<f64>>(16)?
+                .unwrap_or(if is_correct { 1.0 } else { 0.0 }),
+            feedback: row
+                .get::<_, Option<String>>(17)?
+                .and_then(|s| serde_json::from_str(&s).ok())
+                .unwrap_or_default(),
+            stability: Self::read_f64(row, 11)?,
+            difficulty: Self::read_f64(row, 12)?,
+            elapsed_days: row.get(13)?,
+            scheduled_days: row.get(14)?,
+            review_state: serde_json::from_str(&row.get::<_, String>(15)?).unwrap(),
+        })
+    }
+
     pub fn get_attempts_by_item(&self, item_id: &str) -> Result<Vec<Attempt>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM attempts WHERE item_id = ?1 ORDER BY attempted_at DESC",
+            Self::ATTEMPT_COLUMNS
+        ))?;
+        let attempts = stmt
+            .query_map(params![item_id], Self::row_to_attempt)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(attempts)
+    }
+
+    /// Attempts on each concept's items, keyed by concept id and newest first.
+    pub fn get_attempts_by_concept(&self) -> Result<HashMap<String, Vec<Attempt>>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {}, concept_id FROM attempts JOIN item_concepts USING (item_id)
+             ORDER BY attempted_at DESC",
+            Self::ATTEMPT_COLUMNS
+        ))?;
+        let mut rows = stmt.query([])?;
+        let mut by_concept: HashMap<String, Vec<Attempt>> = HashMap::new();
+        while let Some(row) = rows.next()? {
+            by_concept.entry(row.get(18)?).or_default().push(Self::row_to_attempt(row)?);
+        }
+        Ok(by_concept)
+    }
+
+    /// Mean `time_spent_ms` per attempted item, ignoring attempts with no time recorded.
+    pub fn get_average_time_by_item(&self) -> Result<HashMap<String, f64>> {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare(
-            "SELECT id, item_id, session_id, user_answer, is_correct, confidence, time_spent_ms, attempted_at, 
-                    sir_phase, next_review_date, metacognitive, stability, difficulty, elapsed_days, scheduled_days, review_state 
-             FROM attempts WHERE item_id = ?1 ORDER BY attempted_at DESC"
+            "SELECT item_id, AVG(time_spent_ms) FROM attempts WHERE time_spent_ms > 0 GROUP BY item_id",
         )?;
+        let averages = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<HashMap<_, _>, _>>()?;
+        Ok(averages)
+    }
+
+    /// Attempts made in a session, oldest first.
+    pub fn get_attempts_by_session(&self, session_id: &str) -> Result<Vec<Attempt>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM attempts WHERE session_id = ?1 ORDER BY attempted_at, id",
+            Self::ATTEMPT_COLUMNS
+        ))?;
         let attempts = stmt
-            .query_map(params![item_id], |row| {
-                Ok(Attempt {
-                    id: row.get(0)?,
-                    item_id: row.get(1)?,
-                    session_id: row.get(2)?,
-                    user_answer: row.get(3)?,
-                    is_correct: row.get(4)?,
-                    confidence: row.get(5)?,
-                    time_spent_ms: row.get(6)?,
-                    attempted_at: row.get::<_, String>(7)?.parse().unwrap(),
-                    sir_phase: serde_json::from_str(&row.get::<_, String>(8)?).unwrap_or(SirPhase::Encoding),
-                    next_review_date: row.get::<_, String>(9)?.parse().unwrap_or_else(|_| chrono::Utc::now()),
-                    metacognitive: row
-                        .get::<_, Option<String>>(10)?
-                        .and_then(|s| serde_json::from_str(&s).ok()),
-                    stability: Self::read_f64(row, 11)?,
-                    difficulty: Self::read_f64(row, 12)?,
-                    elapsed_days: row.get(13)?,
-                    scheduled_days: row.get(14)?,
-                    review_state: serde_json::from_str(&row.get::<_, String>(15)?).unwrap(),
-                })
-            })?
+            .query_map(params![session_id], Self::row_to_attempt)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(attempts)
+    }
+
+    /// Every attempt, grouped by item and oldest first within each item.
+    pub fn get_all_attempts(&self) -> Result<Vec<Attempt>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM attempts ORDER BY item_id, attempted_at",
+            Self::ATTEMPT_COLUMNS
+        ))?;
+        let attempts = stmt
+            .query_map([], Self::row_to_attempt)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(attempts)
+    }
+
+    /// Attempts matching `filter`, oldest first.
+    pub fn get_attempts_filtered(&self, filter: &AttemptFilter) -> Result<Vec<Attempt>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM attempts
+             WHERE (?1 IS NULL OR item_id IN (SELECT item_id FROM item_concepts WHERE concept_id = ?1))
+               AND (?2 IS NULL OR item_id IN (
+                    SELECT ic.item_id FROM item_concepts ic
+                    JOIN concepts c ON c.id = ic.concept_id
+                    WHERE c.domain = ?2))
+               AND (?3 IS NULL OR julianday(attempted_at) >= julianday(?3))
+               AND (?4 IS NULL OR julianday(attempted_at) <= julianday(?4))
+             ORDER BY julianday(attempted_at), id",
+            Self::ATTEMPT_COLUMNS
+        ))?;
+        let attempts = stmt
+            .query_map(
+                params![
+                    filter.concept_id,
+                    filter.domain,
+                    filter.from.map(|d| d.to_rfc3339()),
+                    filter.to.map(|d| d.to_rfc3339())
+                ],
+                Self::row_to_attempt,
+            )?
             .collect::<Result<Vec<_>, _>>()?;
         Ok(attempts)
     }
@@ -357,6 +668,48 @@ impl Database {
         Ok(())
     }
 
+    /// Creates a session together with its fixed item set.
+    pub fn create_session_with_items(&self, session: &Session, item_ids: &[String]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute("INSERT INTO sessions VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![session.id, serde_json::to_string(&session.session_type)?, session.started_at.to_rfc3339(),
+                    session.completed_at.map(|dt| dt.to_rfc3339()), session.total_items, session.completed_items,
+                    session.accuracy, session.average_confidence])?;
+        {
+            let mut stmt = tx.prepare("INSERT INTO session_items (session_id, item_id, position) VALUES (?1, ?2, ?3)")?;
+            for (position, item_id) in item_ids.iter().enumerate() {
+                stmt.execute(params![session.id, item_id, position as i64])
+                    .with_context(|| format!("Item {} does not exist", item_id))?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// The session's fixed item set in drawing order; empty for sessions without one.
+    pub fn get_session_items(&self, session_id: &str) -> Result<Vec<Item>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM items JOIN session_items s ON s.item_id = items.id
+             WHERE s.session_id = ?1 ORDER BY s.position",
+            Self::ITEM_COLUMNS
+        ))?;
+        let items = stmt.query_map([session_id], Self::row_to_item)?.collect::<Result<Vec<_>, _>>()?;
+        Ok(items)
+    }
+
+    pub fn get_all_session_items(&self) -> Result<Vec<SessionItem>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT session_id, item_id, position FROM session_items ORDER BY session_id, position",
+        )?;
+        let links = stmt
+            .query_map([], |row| Ok(SessionItem { session_id: row.get(0)?, item_id: row.get(1)?, position: row.get(2)? }))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(links)
+    }
+
     pub fn update_session(&self, session: &Session) -> Result<()> {
         let conn = self.conn.lock().unwrap();
         conn.execute("UPDATE sessions SET completed_at=?2, completed_items=?3, accuracy=?4, average_confidence=?5 WHERE id=?1",
@@ -397,8 +750,252 @@ impl Database {
         Ok(sessions)
     }
 
+    pub fn save_concept_ability(&self, estimate: &ConceptAbility) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO concept_abilities VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                estimate.concept_id,
+                estimate.ability,
+                estimate.standard_error,
+                estimate.items_used,
+                estimate.session_id,
+                estimate.estimated_at.to_rfc3339()
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_concept_abilities(&self) -> Result<Vec<ConceptAbility>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT concept_id, ability, standard_error, items_used, session_id, estimated_at FROM concept_abilities",
+        )?;
+        let estimates = stmt
+            .query_map([], |row| {
+                Ok(ConceptAbility {
+                    concept_id: row.get(0)?,
+                    ability: row.get(1)?,
+                    standard_error: row.get(2)?,
+                    items_used: row.get(3)?,
+                    session_id: row.get(4)?,
+                    estimated_at: row.get::<_, String>(5)?.parse().unwrap(),
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(estimates)
+    }
+
+    pub fn get_fsrs_parameters(&self) -> Result<Option<FSRSParameters>> {
+        let conn = self.conn.lock().unwrap();
+        let value: Option<String> = conn
+            .query_row("SELECT value FROM settings WHERE key = 'fsrs_parameters'", [], |row| row.get(0))
+            .optional()?;
+        value
+            .map(|v| serde_json::from_str(&v).context("Stored FSRS parameters are invalid"))
+            .transpose()
+    }
+
+    pub fn save_fsrs_parameters(&self, params: &FSRSParameters) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES ('fsrs_parameters', ?1)",
+            params![serde_json::to_string(params)?],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_confidence_mapping(&self) -> Result<Option<ConfidenceMapping>> {
+        let conn = self.conn.lock().unwrap();
+        let value: Option<String> = conn
+            .query_row("SELECT value FROM settings WHERE key = 'confidence_mapping'", [], |row| row.get(0))
+            .optional()?;
+        value
+            .map(|v| serde_json::from_str(&v).context("Stored confidence mapping is invalid"))
+            .transpose()
+    }
+
+    pub fn save_confidence_mapping(&self, mapping: &ConfidenceMapping) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES ('confidence_mapping', ?1)",
+            params![serde_json::to_string(mapping)?],
+        )?;
+        Ok(())
+    }
+
+    pub fn export_collection(&self) -> Result<CollectionData> {
+        Ok(CollectionData {
+            learning_materials: self.get_all_learning_materials()?,
+            concepts: self.get_all_concepts()?,
+            items: self.get_all_items()?,
+            attempts: self.get_all_attempts()?,
+            sessions: self.get_all_sessions()?,
+            concept_edges: self.get_concept_edges()?,
+            session_items: self.get_all_session_items()?,
+        })
+    }
+
+    /// Loads `data` in a single transaction. `Replace` empties every table first;
+    /// `Merge` upserts by id. Per-item review state is recomputed from the
+    /// resulting attempt history either way.
+    pub fn restore_collection(&self, data: &CollectionData, mode: RestoreMode) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        if mode == RestoreMode::Replace {
+            tx.execute_batch(
+                "DELETE FROM item_states;
+                DELETE FROM concept_abilities;
+                DELETE FROM item_concepts;
+                DELETE FROM concept_edges;
+                DELETE FROM session_items;
+                DELETE FROM attempts;
+                DELETE FROM sessions;
+                DELETE FROM items;
+                DELETE FROM concepts;
+                DELETE FROM learning_materials;",
+            )?;
+        }
+
+        {
+            let mut stmt = tx.prepare(&Self::upsert_sql(
+                "learning_materials",
+                &["id", "content", "domain", "encoding_date", "created_at"],
+            ))?;
+            for material in &data.learning_materials {
+                stmt.execute(params![
+                    material.id,
+                    material.content,
+                    material.domain,
+                    material.encoding_date.to_rfc3339(),
+                    material.created_at.to_rfc3339()
+                ])?;
+            }
+
+            let mut stmt = tx.prepare(&Self::upsert_sql(
+                "concepts",
+                &["id", "name", "domain", "subdomain", "description", "tags", "learning_material_id", "created_at", "updated_at"],
+            ))?;
+            for concept in &data.concepts {
+                stmt.execute(params![
+                    concept.id,
+                    concept.name,
+                    concept.domain,
+                    concept.subdomain,
+                    concept.description,
+                    serde_json::to_string(&concept.tags)?,
+                    concept.learning_material_id,
+                    concept.created_at.to_rfc3339(),
+                    concept.updated_at.to_rfc3339()
+                ])?;
+            }
+
+            let mut stmt = tx.prepare(
+                "INSERT OR IGNORE INTO concept_edges (from_concept_id, to_concept_id, relation, created_at)
+                 VALUES (?1, ?2, ?3, ?4)",
+            )?;
+            for edge in &data.concept_edges {
+                stmt.execute(params![edge.from_concept_id, edge.to_concept_id, edge.relation.as_str(), edge.created_at.to_rfc3339()])
+                    .with_context(|| format!("Concept edge {} -> {} links to a missing concept", edge.from_concept_id, edge.to_concept_id))?;
+            }
+
+            let mut stmt = tx.prepare(&Self::upsert_sql(
+                "items",
+                &["id", "stem", "item_type", "difficulty", "source", "explanation", "created_at", "updated_at", "discrimination"],
+            ))?;
+            for item in &data.items {
+                stmt.execute(params![item.id, item.stem, serde_json::to_string(&item.item_type)?,
+                    item.difficulty, item.source, item.explanation, item.created_at.to_rfc3339(), item.updated_at.to_rfc3339(),
+                    item.discrimination])?;
+                Self::link_concepts(&tx, &item.id, &item.concept_ids)
+                    .with_context(|| format!("Item {} links to a missing concept", item.id))?;
+            }
+
+            let mut stmt = tx.prepare(&Self::upsert_sql(
+                "sessions",
+                &["id", "session_type", "started_at", "completed_at", "total_items", "completed_items", "accuracy", "average_confidence"],
+            ))?;
+            for session in &data.sessions {
+                stmt.execute(params![session.id, serde_json::to_string(&session.session_type)?, session.started_at.to_rfc3339(),
+                    session.completed_at.map(|dt| dt.to_rfc3339()), session.total_items, session.completed_items,
+                    session.accuracy, session.average_confidence])?;
+            }
+
+            let mut stmt = tx.prepare(
+                "INSERT OR IGNORE INTO session_items (session_id, item_id, position) VALUES (?1, ?2, ?3)",
+            )?;
+            for link in &data.session_items {
+                stmt.execute(params![link.session_id, link.item_id, link.position])
+                    .with_context(|| format!("Session {} lists a missing item", link.session_id))?;
+            }
+
+            let mut stmt = tx.prepare(&Self::upsert_sql(
+                "attempts",
+                &["id", "item_id", "session_id", "user_answer", "is_correct", "confidence", "time_spent_ms", "attempted_at",
+                  "sir_phase", "next_review_date", "metacognitive", "stability", "difficulty", "elapsed_days", "scheduled_days",
+                  "review_state", "score", "feedback"],
+            ))?;
+            for attempt in &data.attempts {
+                stmt.execute(params![
+                    attempt.id,
+                    attempt.item_id,
+                    attempt.session_id,
+                    attempt.user_answer,
+                    attempt.is_correct,
+                    attempt.confidence,
+                    attempt.time_spent_ms,
+                    attempt.attempted_at.to_rfc3339(),
+                    serde_json::to_string(&attempt.sir_phase)?,
+                    attempt.next_review_date.to_rfc3339(),
+                    attempt.metacognitive.as_ref().map(|m| serde_json::to_string(m).unwrap()),
+                    attempt.stability,
+                    attempt.difficulty,
+                    attempt.elapsed_days,
+                    attempt.scheduled_days,
+                    serde_json::to_string(&attempt.review_state)?,
+                    attempt.score,
+                    serde_json::to_string(&attempt.feedback)?
+                ])?;
+            }
+        }
+
+        tx.execute_batch(
+            "DELETE FROM item_states;
+            INSERT INTO item_states
+            SELECT a.item_id, a.id, a.attempted_at, a.stability, a.difficulty, a.review_state, a.sir_phase,
+                   a.scheduled_days, a.next_review_date,
+                   (SELECT COUNT(*) FROM attempts c WHERE c.item_id = a.item_id),
+                   (SELECT COUNT(*) FROM attempts c WHERE c.item_id = a.item_id
+                       AND c.is_correct = 0 AND c.review_state = '\"Relearning\"')
+            FROM attempts a
+            WHERE a.id = (SELECT b.id FROM attempts b WHERE b.item_id = a.item_id
+                          ORDER BY b.attempted_at DESC, b.id DESC LIMIT 1);",
+        )?;
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// `INSERT ... ON CONFLICT(id) DO UPDATE` over every listed column.
+    fn upsert_sql(table: &str, columns: &[&str]) -> String {
+        let placeholders: Vec<String> = (1..=columns.len()).map(|i| format!("?{}", i)).collect();
+        let updates: Vec<String> = columns[1..].iter().map(|c| format!("{c} = excluded.{c}")).collect();
+        format!(
+            "INSERT INTO {} ({}) VALUES ({}) ON CONFLICT(id) DO UPDATE SET {}",
+            table,
+            columns.join(", "),
+            placeholders.join(", "),
+            updates.join(", ")
+        )
+    }
+
     pub fn clear_all(&self) -> Result<()> {
         let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM item_states", [])?;
+        conn.execute("DELETE FROM concept_abilities", [])?;
+        conn.execute("DELETE FROM item_concepts", [])?;
+        conn.execute("DELETE FROM session_items", [])?;
         conn.execute("DELETE FROM attempts", [])?;
         conn.execute("DELETE FROM sessions", [])?;
         conn.execute("DELETE FROM items", [])?;
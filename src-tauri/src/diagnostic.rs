@@ -0,0 +1,145 @@
+use crate::database::Database;
+use crate::exam;
+use crate::irt::{difficulty_logit, probability};
+use crate::models::*;
+use crate::session_report;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
+
+/// A concept's estimate is precise enough once its standard error drops here.
+pub const TARGET_STANDARD_ERROR: f64 = 0.4;
+/// Items asked per concept before moving on regardless of precision.
+const MAX_ITEMS_PER_CONCEPT: usize = 8;
+/// Half-width of the ability grid, in logits.
+const GRID_LIMIT: f64 = 4.0;
+const GRID_POINTS: usize = 81;
+
+/// Expected-a-posteriori ability and its posterior standard deviation, from
+/// `(difficulty logit, discrimination, correct)` responses under a standard normal prior. The
+/// prior keeps all-correct or all-wrong runs finite.
+pub fn estimate(responses: &[(f64, f64, bool)]) -> (f64, f64) {
+    let step = 2.0 * GRID_LIMIT / (GRID_POINTS - 1) as f64;
+    let mut weights = Vec::with_capacity(GRID_POINTS);
+    for i in 0..GRID_POINTS {
+        let theta = -GRID_LIMIT + i as f64 * step;
+        let mut log_density = -0.5 * theta * theta;
+        for &(difficulty, discrimination, correct) in responses {
+            let p = probability(theta, difficulty, discrimination);
+            log_density += if correct { p.ln() } else { (1.0 - p).ln() };
+        }
+        weights.push((theta, log_density));
+    }
+
+    let max = weights.iter().map(|(_, l)| *l).fold(f64::NEG_INFINITY, f64::max);
+    let total: f64 = weights.iter().map(|(_, l)| (l - max).exp()).sum();
+    let mean = weights.iter().map(|(t, l)| t * (l - max).exp()).sum::<f64>() / total;
+    let variance = weights.iter().map(|(t, l)| (t - mean).powi(2) * (l - max).exp()).sum::<f64>() / total;
+    (mean, variance.sqrt())
+}
+
+/// Responses in the session grouped by every concept the answered item is linked to.
+fn responses_by_concept<'a>(
+    attempts: &[Attempt],
+    items: &'a HashMap<String, Item>,
+) -> HashMap<&'a str, Vec<(f64, f64, bool)>> {
+    let mut responses: HashMap<&str, Vec<(f64, f64, bool)>> = HashMap::new();
+    for attempt in attempts {
+        let Some(item) = items.get(&attempt.item_id) else { continue };
+        for concept_id in &item.concept_ids {
+            responses
+                .entry(concept_id.as_str())
+                .or_default()
+                .push((difficulty_logit(item.difficulty), item.discrimination, attempt.is_correct));
+        }
+    }
+    responses
+}
+
+/// Picks the next item of a diagnostic session. The concept being measured
+/// keeps going until its standard error reaches `TARGET_STANDARD_ERROR`, it
+/// has had `MAX_ITEMS_PER_CONCEPT` items or its pool runs out; then the next
+/// concept starts, least-known first. Within a concept the unused item with
+/// the most information at the current estimate is chosen. When the session
+/// reaches `total_items` (if positive) or no concept is left it is completed
+/// and `None` is returned.
+pub fn next_item(db: &Database, session: &Session, now: DateTime<Utc>) -> Result<Option<Item>> {
+    if session.completed_at.is_some() {
+        return Ok(None);
+    }
+    let attempts = db.get_attempts_by_session(&session.id)?;
+    if session.total_items > 0 && attempts.len() as i32 >= session.total_items {
+        session_report::complete(db, session, now)?;
+        return Ok(None);
+    }
+
+    let items: HashMap<String, Item> = db.get_all_items()?.into_iter().map(|i| (i.id.clone(), i)).collect();
+    let answered: HashSet<&str> = attempts.iter().map(|a| a.item_id.as_str()).collect();
+    let responses = responses_by_concept(&attempts, &items);
+    let known: HashMap<String, ConceptAbility> =
+        db.get_concept_abilities()?.into_iter().map(|a| (a.concept_id.clone(), a)).collect();
+
+    // Items drawn for a running exam stay out until it ends
+    let in_exam = exam::open_item_ids(db, now)?;
+    let mut pools: HashMap<&str, Vec<&Item>> = HashMap::new();
+    for item in items.values().filter(|i| !answered.contains(i.id.as_str()) && !in_exam.contains(&i.id)) {
+        for concept_id in &item.concept_ids {
+            pools.entry(concept_id.as_str()).or_default().push(item);
+        }
+    }
+
+    let unfinished = |concept_id: &str| {
+        let asked = responses.get(concept_id).map(Vec::as_slice).unwrap_or_default();
+        let precise = !asked.is_empty() && estimate(asked).1 <= TARGET_STANDARD_ERROR;
+        !precise && asked.len() < MAX_ITEMS_PER_CONCEPT && pools.contains_key(concept_id)
+    };
+
+    // Finish a concept already under way before starting another
+    let mut candidates: Vec<&str> = pools.keys().copied().filter(|id| unfinished(id)).collect();
+    candidates.sort_by(|a, b| {
+        let started = |id: &str| responses.contains_key(id);
+        let prior_error = |id: &str| known.get(id).map(|k| k.standard_error).unwrap_or(f64::INFINITY);
+        started(b)
+            .cmp(&started(a))
+            .then(prior_error(b).total_cmp(&prior_error(a)))
+            .then(a.cmp(b))
+    });
+
+    let Some(concept_id) = candidates.first() else {
+        session_report::complete(db, session, now)?;
+        return Ok(None);
+    };
+    let asked = responses.get(concept_id).map(Vec::as_slice).unwrap_or_default();
+    let (ability, _) = estimate(asked);
+    let best = pools[concept_id]
+        .iter()
+        .map(|item| {
+            let p = probability(ability, difficulty_logit(item.difficulty), item.discrimination);
+            (item.discrimination.powi(2) * p * (1.0 - p), *item)
+        })
+        .max_by(|(a, x), (b, y)| a.total_cmp(b).then_with(|| y.id.cmp(&x.id)))
+        .map(|(_, item)| item.clone());
+    Ok(best)
+}
+
+/// Re-estimates every concept linked to `item` from the session's responses
+/// and stores the results.
+pub fn record(db: &Database, session: &Session, item: &Item, now: DateTime<Utc>) -> Result<()> {
+    let attempts = db.get_attempts_by_session(&session.id)?;
+    let items: HashMap<String, Item> = db.get_all_items()?.into_iter().map(|i| (i.id.clone(), i)).collect();
+    let responses = responses_by_concept(&attempts, &items);
+
+    for concept_id in &item.concept_ids {
+        let Some(asked) = responses.get(concept_id.as_str()) else { continue };
+        let (ability, standard_error) = estimate(asked);
+        db.save_concept_ability(&ConceptAbility {
+            concept_id: concept_id.clone(),
+            ability,
+            standard_error,
+            items_used: asked.len() as i32,
+            session_id: Some(session.id.clone()),
+            estimated_at: now,
+        })?;
+    }
+    Ok(())
+}
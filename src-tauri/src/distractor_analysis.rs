@@ -0,0 +1,150 @@
+use crate::models::{Attempt, ConfidenceMapping, DistractorReport, Item, ItemType, McqOption, OptionStats};
+use crate::sir_scheduler::SirScheduler;
+use std::collections::HashMap;
+
+/// Mastery (0-100) of the item's concepts at or above which a pick counts as
+/// made by a high-mastery learner.
+const HIGH_MASTERY: f64 = 80.0;
+/// Earlier attempts on a concept's other items needed before its mastery at
+/// the time of a pick is trusted.
+const MIN_PRIOR_ATTEMPTS: usize = 3;
+/// Most recent earlier attempts per concept used for that mastery.
+const MASTERY_WINDOW: usize = 50;
+/// Attempts an item needs before its options are flagged.
+const MIN_ATTEMPTS: i32 = 20;
+/// A distractor chosen less often than this is non-functioning.
+const NON_FUNCTIONING_RATE: f64 = 0.05;
+
+const NON_FUNCTIONING: &str = "non_functioning";
+const CHOSEN_OVER_KEY: &str = "chosen_over_key";
+
+/// Option statistics for every MCQ item in `items`, flagged items first.
+/// `attempts` must be oldest first. A pick is high-mastery when every concept
+/// of the item was at or above `HIGH_MASTERY` just before it, judged from the
+/// concept's earlier attempts on other items so that remembering this item
+/// does not count. Answers naming no current option (the option was edited or
+/// removed) are counted as unmatched.
+pub fn analyze(attempts: &[Attempt], items: &[Item], sir: &SirScheduler, mapping: &ConfidenceMapping) -> Vec<DistractorReport> {
+    let item_concepts: HashMap<&str, &[String]> =
+        items.iter().map(|item| (item.id.as_str(), item.concept_ids.as_slice())).collect();
+
+    // Concept history as it stood before each attempt
+    let mut history: HashMap<&str, Vec<&Attempt>> = HashMap::new();
+    let mut high_mastery: HashMap<&str, bool> = HashMap::new();
+    for attempt in attempts {
+        let concept_ids = item_concepts.get(attempt.item_id.as_str()).copied().unwrap_or_default();
+        let high = !concept_ids.is_empty()
+            && concept_ids.iter().all(|concept_id| {
+                let prior: Vec<Attempt> = history
+                    .get(concept_id.as_str())
+                    .into_iter()
+                    .flatten()
+                    .rev()
+                    .filter(|a| a.item_id != attempt.item_id)
+                    .take(MASTERY_WINDOW)
+                    .map(|a| (*a).clone())
+                    .collect();
+                prior.len() >= MIN_PRIOR_ATTEMPTS && sir.calculate_mastery(&prior, mapping) >= HIGH_MASTERY
+            });
+        high_mastery.insert(attempt.id.as_str(), high);
+        for concept_id in concept_ids {
+            history.entry(concept_id.as_str()).or_default().push(attempt);
+        }
+    }
+
+    let mut by_item: HashMap<&str, Vec<&Attempt>> = HashMap::new();
+    for attempt in attempts {
+        by_item.entry(attempt.item_id.as_str()).or_default().push(attempt);
+    }
+
+    let mut reports: Vec<DistractorReport> = items
+        .iter()
+        .filter_map(|item| match &item.item_type {
+            ItemType::Mcq { options } => {
+                let picks = by_item.get(item.id.as_str()).map(Vec::as_slice).unwrap_or_default();
+                Some(report(item, options, picks, &high_mastery))
+            }
+            _ => None,
+        })
+        .collect();
+    reports.sort_by(|a, b| {
+        a.flags
+            .is_empty()
+            .cmp(&b.flags.is_empty())
+            .then(b.attempts.cmp(&a.attempts))
+            .then_with(|| a.stem.cmp(&b.stem))
+    });
+    reports
+}
+
+fn report(item: &Item, options: &[McqOption], picks: &[&Attempt], high_mastery: &HashMap<&str, bool>) -> DistractorReport {
+    let attempts = picks.len() as i32;
+    let high_attempts = picks.iter().filter(|a| high_mastery[a.id.as_str()]).count() as i32;
+    let rate = |count: i32, total: i32| if total > 0 { count as f64 / total as f64 } else { 0.0 };
+
+    let mut unmatched = 0;
+    let mut counts: HashMap<&str, (i32, i32)> = HashMap::new();
+    for attempt in picks {
+        let answer = attempt.user_answer.trim();
+        // Older attempts may hold the option text rather than its id
+        let chosen = options
+            .iter()
+            .find(|o| o.id == answer)
+            .or_else(|| options.iter().find(|o| o.text.trim().eq_ignore_ascii_case(answer)));
+        match chosen {
+            Some(option) => {
+                let (all, high) = counts.entry(option.id.as_str()).or_default();
+                *all += 1;
+                *high += high_mastery[attempt.id.as_str()] as i32;
+            }
+            None => unmatched += 1,
+        }
+    }
+
+    let key_selections = options
+        .iter()
+        .filter(|o| o.is_correct)
+        .map(|o| counts.get(o.id.as_str()).map_or(0, |c| c.0))
+        .max()
+        .unwrap_or(0);
+    let enough = attempts >= MIN_ATTEMPTS;
+    let options: Vec<OptionStats> = options
+        .iter()
+        .map(|option| {
+            let (selections, high_selections) = counts.get(option.id.as_str()).copied().unwrap_or_default();
+            let selection_rate = rate(selections, attempts);
+            let flag = if !enough || option.is_correct {
+                None
+            } else if selections > key_selections {
+                Some(CHOSEN_OVER_KEY.to_string())
+            } else if selection_rate < NON_FUNCTIONING_RATE {
+                Some(NON_FUNCTIONING.to_string())
+            } else {
+                None
+            };
+            OptionStats {
+                option_id: option.id.clone(),
+                text: option.text.clone(),
+                is_correct: option.is_correct,
+                selections,
+                selection_rate,
+                high_mastery_selections: high_selections,
+                high_mastery_rate: rate(high_selections, high_attempts),
+                flag,
+            }
+        })
+        .collect();
+
+    let mut flags: Vec<String> = options.iter().filter_map(|o| o.flag.clone()).collect();
+    flags.sort();
+    flags.dedup();
+    DistractorReport {
+        item_id: item.id.clone(),
+        stem: item.stem.clone(),
+        attempts,
+        high_mastery_attempts: high_attempts,
+        unmatched,
+        options,
+        flags,
+    }
+}
@@ -0,0 +1,130 @@
+use crate::grading::{self, CALC_TOLERANCE};
+use crate::models::{AnswerError, Attempt, ErrorAnalysis, Item, ItemType};
+use crate::units;
+use std::collections::{HashMap, HashSet};
+
+/// Confidence (1-5) at or above which a miss counts as a high-confidence error.
+const HIGH_CONFIDENCE: i32 = 4;
+/// Misses that took longer than this count as timeouts.
+const TIMEOUT_MS: i64 = 120_000;
+/// Item ids listed per error type.
+const MAX_EXAMPLES: usize = 5;
+
+const WRONG_DISTRACTOR: &str = "wrong_distractor";
+const REPEATED_DISTRACTOR: &str = "repeated_distractor";
+const UNIT_ERROR: &str = "unit_error";
+const MAGNITUDE_ERROR: &str = "magnitude_error";
+const CALCULATION_ERROR: &str = "calculation_error";
+const PARTIAL_CASE: &str = "partial_case";
+const FAILED_CASE: &str = "failed_case";
+const PARTIAL_CLOZE: &str = "partial_cloze";
+const PARTIAL_RECALL: &str = "partial_recall";
+const WRONG_ANSWER: &str = "wrong_answer";
+const HIGH_CONFIDENCE_ERROR: &str = "high_confidence";
+const TIMEOUT: &str = "timeout";
+
+/// Classifies every missed attempt and tallies the error types, most common
+/// first. Each miss gets one content type (what was wrong with the answer) and
+/// may also be a high-confidence error or a timeout. `attempts` must be oldest
+/// first so a distractor picked again on the same item reads as repeated.
+pub fn analyze(attempts: &[Attempt], items: &HashMap<String, Item>) -> Vec<ErrorAnalysis> {
+    let mut tallies: HashMap<&'static str, (i32, Vec<String>)> = HashMap::new();
+    let mut picked: HashSet<(&str, &str)> = HashSet::new();
+    let mut misses = 0;
+
+    for attempt in attempts.iter().filter(|a| !a.is_correct) {
+        misses += 1;
+        let mut types = vec![classify(attempt, items.get(&attempt.item_id), &picked)];
+        if attempt.confidence >= HIGH_CONFIDENCE {
+            types.push(HIGH_CONFIDENCE_ERROR);
+        }
+        if attempt.time_spent_ms > TIMEOUT_MS {
+            types.push(TIMEOUT);
+        }
+        picked.insert((attempt.item_id.as_str(), attempt.user_answer.as_str()));
+
+        for error_type in types {
+            let (count, examples) = tallies.entry(error_type).or_default();
+            *count += 1;
+            // Most recent first
+            examples.retain(|id| id != &attempt.item_id);
+            examples.insert(0, attempt.item_id.clone());
+            examples.truncate(MAX_EXAMPLES);
+        }
+    }
+
+    let mut analysis: Vec<ErrorAnalysis> = tallies
+        .into_iter()
+        .map(|(error_type, (count, example_items))| ErrorAnalysis {
+            error_type: error_type.to_string(),
+            count,
+            percentage: count as f64 / misses as f64 * 100.0,
+            example_items,
+        })
+        .collect();
+    analysis.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.error_type.cmp(&b.error_type)));
+    analysis
+}
+
+fn classify(attempt: &Attempt, item: Option<&Item>, picked: &HashSet<(&str, &str)>) -> &'static str {
+    let Some(item) = item else { return WRONG_ANSWER };
+    let some_parts_right = attempt.feedback.iter().any(|f| f.is_correct);
+
+    match &item.item_type {
+        ItemType::Mcq { .. } => {
+            if picked.contains(&(attempt.item_id.as_str(), attempt.user_answer.as_str())) {
+                REPEATED_DISTRACTOR
+            } else {
+                WRONG_DISTRACTOR
+            }
+        }
+        ItemType::Calculation { tolerance, .. } => {
+            match attempt.feedback.first().and_then(|f| f.error.as_ref()) {
+                Some(AnswerError::WrongUnit | AnswerError::IncompatibleUnit | AnswerError::UnknownUnit) => UNIT_ERROR,
+                Some(AnswerError::OutOfTolerance) => {
+                    let tolerance = tolerance.unwrap_or(CALC_TOLERANCE);
+                    if is_off_by_power_of_ten(attempt, tolerance) {
+                        MAGNITUDE_ERROR
+                    } else {
+                        CALCULATION_ERROR
+                    }
+                }
+                None => WRONG_ANSWER,
+            }
+        }
+        ItemType::CaseVignette { .. } if some_parts_right => PARTIAL_CASE,
+        ItemType::CaseVignette { .. } => FAILED_CASE,
+        ItemType::Cloze { .. } if some_parts_right => PARTIAL_CLOZE,
+        ItemType::FreeRecall { .. } if attempt.score > 0.0 => PARTIAL_RECALL,
+        ItemType::Cloze { .. } | ItemType::FreeRecall { .. } => WRONG_ANSWER,
+    }
+}
+
+/// True when the answer, converted to the key's unit, matches the key scaled
+/// by 10^k for some k in -6..=6 other than zero, e.g. mg entered as mcg or a
+/// slipped decimal point. The key is read from the feedback so randomized items
+/// are judged against the numbers the learner actually saw.
+fn is_off_by_power_of_ten(attempt: &Attempt, tolerance: f64) -> bool {
+    let Some(feedback) = attempt.feedback.first() else { return false };
+    let (Some((expected, expected_unit)), Some((given, given_unit))) =
+        (split_quantity(&feedback.expected), split_quantity(&attempt.user_answer))
+    else {
+        return false;
+    };
+    let given_unit = if given_unit.is_empty() { expected_unit } else { given_unit };
+    let given = units::convert(given, given_unit, expected_unit).unwrap_or(given);
+    if expected == 0.0 || given == 0.0 {
+        return false;
+    }
+
+    (-6..=6)
+        .filter(|k| *k != 0)
+        .any(|k| grading::within_tolerance(expected * 10f64.powi(k), given, tolerance))
+}
+
+/// Splits "12.5 mg/kg" into its number and unit.
+fn split_quantity(text: &str) -> Option<(f64, &str)> {
+    let text = text.trim();
+    let (number, unit) = text.split_once(' ').unwrap_or((text, ""));
+    Some((number.parse().ok()?, unit.trim()))
+}
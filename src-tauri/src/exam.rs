@@ -0,0 +1,322 @@
+use crate::database::Database;
+use crate::models::*;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use rand::seq::SliceRandom;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Starts an exam over `count` items drawn at random from the whole collection.
+/// The draw is stored with the session, so the item set cannot change afterwards.
+pub fn start(db: &Database, time_limit_ms: i64, count: i32) -> Result<Session> {
+    if time_limit_ms <= 0 {
+        bail!("Exam time limit must be positive");
+    }
+    if count <= 0 {
+        bail!("Exam needs at least one item");
+    }
+
+    let mut item_ids: Vec<String> = db.get_all_items()?.into_iter().map(|item| item.id).collect();
+    if item_ids.is_empty() {
+        bail!("There are no items to draw an exam from");
+    }
+    item_ids.shuffle(&mut rand::thread_rng());
+    item_ids.truncate(count as usize);
+
+    let session = Session::new(SessionType::Exam { time_limit_ms }, item_ids.len() as i32);
+    db.create_session_with_items(&session, &item_ids)?;
+    Ok(session)
+}
+
+/// Checks that `item_id` may still be answered in the exam. An exam found past
+/// its deadline is completed on the spot and the answer is refused.
+pub fn check_submission(db: &Database, session: &Session, item_id: &str, now: DateTime<Utc>) -> Result<()> {
+    if session.completed_at.is_some() {
+        bail!("This exam has already been completed");
+    }
+    if let Some(deadline) = session.deadline().filter(|deadline| now > *deadline) {
+        complete(db, session, deadline)?;
+        bail!("Exam time is up; the exam has been completed");
+    }
+    if !db.get_session_items(&session.id)?.iter().any(|item| item.id == item_id) {
+        bail!("Item is not part of this exam");
+    }
+    if db.get_attempts_by_session(&session.id)?.iter().any(|a| a.item_id == item_id) {
+        bail!("Item has already been answered in this exam");
+    }
+    Ok(())
+}
+
+/// Completes the exam once every item has been answered. Returns whether the
+/// exam is now complete.
+pub fn complete_if_answered(db: &Database, session: &Session, now: DateTime<Utc>) -> Result<bool> {
+    let answered = db.get_attempts_by_session(&session.id)?.len() as i32;
+    if answered < session.total_items {
+        return Ok(false);
+    }
+    complete(db, session, now)?;
+    Ok(true)
+}
+
+/// The attempt as returned to the learner: graded once its submission
+/// completes the exam, with the grade withheld while items remain.
+pub fn finish_submission(db: &Database, session: &Session, attempt: Attempt, now: DateTime<Utc>) -> Result<Attempt> {
+    if complete_if_answered(db, session, now)? {
+        Ok(attempt)
+    } else {
+        Ok(withhold_grade(attempt))
+    }
+}
+
+/// Records completion at `at`, or at the deadline if that came first. Stats
+/// come from the stored attempts; accuracy is over every exam item, so
+/// unanswered items count as wrong.
+pub fn complete(db: &Database, session: &Session, at: DateTime<Utc>) -> Result<Session> {
+    let attempts = db.get_attempts_by_session(&session.id)?;
+    let mut session = session.clone();
+    session.completed_at = Some(session.deadline().map_or(at, |deadline| at.min(deadline)));
+    session.completed_items = attempts.len() as i32;
+    session.accuracy = if session.total_items > 0 {
+        attempts.iter().filter(|a| a.is_correct).count() as f64 / session.total_items as f64
+    } else {
+        0.0
+    };
+    session.average_confidence = if attempts.is_empty() {
+        0.0
+    } else {
+        attempts.iter().map(|a| a.confidence as f64).sum::<f64>() / attempts.len() as f64
+    };
+    db.update_session(&session)?;
+    Ok(session)
+}
+
+/// Removes explanations, worked solutions and answer keys so an open exam
+/// shows questions only.
+pub fn withhold_answers(mut item: Item) -> Item {
+    item.explanation.clear();
+    match &mut item.item_type {
+        ItemType::Mcq { options } => options.iter_mut().for_each(|o| {
+            o.is_correct = false;
+            o.explanation = None;
+        }),
+        ItemType::FreeRecall { correct_answer } => correct_answer.clear(),
+        ItemType::Calculation { formula, correct_answer, worked_solution, .. } => {
+            formula.clear();
+            *correct_answer = 0.0;
+            worked_solution.clear();
+        }
+        ItemType::CaseVignette { steps } => steps.iter_mut().for_each(|s| {
+            s.correct_answer.clear();
+            s.explanation.clear();
+        }),
+        ItemType::Cloze { blanks } => blanks.iter_mut().for_each(|b| b.correct_answer.clear()),
+    }
+    item
+}
+
+/// Removes the grade from an attempt made, or shown, while its exam is open.
+pub fn withhold_grade(mut attempt: Attempt) -> Attempt {
+    attempt.is_correct = false;
+    attempt.score = 0.0;
+    attempt.feedback.clear();
+    attempt
+}
+
+/// Items drawn for exams that are still running at `now`.
+pub fn open_item_ids(db: &Database, now: DateTime<Utc>) -> Result<HashSet<String>> {
+    let mut ids = HashSet::new();
+    for session in db.get_all_sessions()? {
+        let open = session.completed_at.is_none() && session.deadline().is_some_and(|deadline| now <= deadline);
+        if open {
+            ids.extend(db.get_session_items(&session.id)?.into_iter().map(|item| item.id));
+        }
+    }
+    Ok(ids)
+}
+
+/// Withholds answers from those of `items` that are in a running exam.
+pub fn withhold_open(db: &Database, items: Vec<Item>, now: DateTime<Utc>) -> Result<Vec<Item>> {
+    let open = open_item_ids(db, now)?;
+    Ok(items
+        .into_iter()
+        .map(|item| if open.contains(&item.id) { withhold_answers(item) } else { item })
+        .collect())
+}
+
+/// Results of a finished exam. An exam past its deadline is completed first;
+/// one still running is refused so results cannot leak answers early.
+pub fn results(db: &Database, session_id: &str, now: DateTime<Utc>) -> Result<ExamResults> {
+    let Some(mut session) = db.get_session(session_id)? else { bail!("Session not found") };
+    let Some(deadline) = session.deadline() else { bail!("Session is not an exam") };
+    if session.completed_at.is_none() {
+        if now <= deadline {
+            bail!("Exam is still in progress");
+        }
+        session = complete(db, &session, deadline)?;
+    }
+
+    let items = db.get_session_items(session_id)?;
+    let attempts = db.get_attempts_by_session(session_id)?;
+    let concepts: HashMap<String, Concept> = db.get_all_concepts()?.into_iter().map(|c| (c.id.clone(), c)).collect();
+    let by_item: HashMap<&str, &Attempt> = attempts.iter().map(|a| (a.item_id.as_str(), a)).collect();
+
+    let mut by_concept: BTreeMap<String, (String, Tally)> = BTreeMap::new();
+    let mut by_domain: BTreeMap<String, (String, Tally)> = BTreeMap::new();
+    for item in &items {
+        let attempt = by_item.get(item.id.as_str()).copied();
+        let mut domains: Vec<&str> = Vec::new();
+        for concept in item.concept_ids.iter().filter_map(|id| concepts.get(id)) {
+            by_concept.entry(concept.id.clone()).or_insert_with(|| (concept.name.clone(), Tally::default())).1.add(attempt);
+            if !domains.contains(&concept.domain.as_str()) {
+                domains.push(concept.domain.as_str());
+            }
+        }
+        for domain in domains {
+            by_domain.entry(domain.to_string()).or_insert_with(|| (domain.to_string(), Tally::default())).1.add(attempt);
+        }
+    }
+
+    let total_items = items.len() as i32;
+    let score = if total_items > 0 {
+        attempts.iter().map(|a| a.score).sum::<f64>() / total_items as f64
+    } else {
+        0.0
+    };
+    Ok(ExamResults {
+        deadline,
+        timed_out: session.completed_at.is_some_and(|at| at >= deadline),
+        total_items,
+        answered: attempts.len() as i32,
+        correct: attempts.iter().filter(|a| a.is_correct).count() as i32,
+        score,
+        by_concept: breakdown(by_concept),
+        by_domain: breakdown(by_domain),
+        attempts,
+        session,
+    })
+}
+
+#[derive(Default)]
+struct Tally {
+    items: i32,
+    answered: i32,
+    correct: i32,
+}
+
+impl Tally {
+    fn add(&mut self, attempt: Option<&Attempt>) {
+        self.items += 1;
+        if let Some(attempt) = attempt {
+            self.answered += 1;
+            self.correct += attempt.is_correct as i32;
+        }
+    }
+}
+
+/// Weakest first, so the report leads with what to study.
+fn breakdown(groups: BTreeMap<String, (String, Tally)>) -> Vec<ExamBreakdown> {
+    let mut rows: Vec<ExamBreakdown> = groups
+        .into_iter()
+        .map(|(key, (label, tally))| ExamBreakdown {
+            key,
+            label,
+            items: tally.items,
+            answered: tally.answered,
+            correct: tally.correct,
+            accuracy: tally.correct as f64 / tally.items as f64,
+        })
+        .collect();
+    rows.sort_by(|a, b| a.accuracy.total_cmp(&b.accuracy).then_with(|| a.label.cmp(&b.label)));
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use std::path::PathBuf;
+
+    fn exam_with_items(count: usize) -> (Database, Session) {
+        let db = Database::new(PathBuf::from(":memory:")).unwrap();
+        let concept = Concept::new("Loop diuretics".into(), "Renal".into());
+        db.create_concept(&concept).unwrap();
+        for n in 0..count {
+            let item_type = ItemType::FreeRecall { correct_answer: format!("answer {}", n) };
+            db.create_item(&Item::new(format!("stem {}", n), item_type, vec![concept.id.clone()], "why".into())).unwrap();
+        }
+        let session = start(&db, 60_000, count as i32).unwrap();
+        (db, session)
+    }
+
+    fn answer(db: &Database, session: &Session, item: &Item, now: DateTime<Utc>) -> Attempt {
+        check_submission(db, session, &item.id, now).unwrap();
+        let mut attempt = Attempt::new(item.id.clone(), Some(session.id.clone()), "answer".into(), true, 4, 1000);
+        attempt.score = 1.0;
+        attempt.feedback = vec![PartFeedback {
+            part_id: "answer".into(),
+            is_correct: true,
+            points_awarded: 1.0,
+            points_possible: 1.0,
+            expected: "answer".into(),
+            message: None,
+            error: None,
+        }];
+        db.create_attempt(&attempt).unwrap();
+        finish_submission(db, session, attempt, now).unwrap()
+    }
+
+    #[test]
+    fn grade_is_withheld_until_the_last_answer() {
+        let (db, session) = exam_with_items(2);
+        let items = db.get_session_items(&session.id).unwrap();
+        let now = Utc::now();
+
+        let first = answer(&db, &session, &items[0], now);
+        assert!(!first.is_correct && first.score == 0.0 && first.feedback.is_empty());
+        assert!(check_submission(&db, &session, &items[0].id, now).is_err());
+        assert!(results(&db, &session.id, now).is_err());
+
+        let last = answer(&db, &session, &items[1], now);
+        assert!(last.is_correct && last.score == 1.0 && !last.feedback.is_empty());
+        let results = results(&db, &session.id, now).unwrap();
+        assert_eq!((results.answered, results.correct), (2, 2));
+        assert!(!results.timed_out);
+    }
+
+    #[test]
+    fn late_submission_is_refused_and_completes_the_exam() {
+        let (db, session) = exam_with_items(3);
+        let items = db.get_session_items(&session.id).unwrap();
+        let now = Utc::now();
+        answer(&db, &session, &items[0], now);
+
+        let late = now + Duration::minutes(2);
+        assert!(check_submission(&db, &session, &items[1].id, late).is_err());
+        let done = db.get_session(&session.id).unwrap().unwrap();
+        assert_eq!(done.completed_at, session.deadline());
+        assert!((done.accuracy - 1.0 / 3.0).abs() < 1e-9);
+
+        let results = results(&db, &session.id, late).unwrap();
+        assert!(results.timed_out);
+        assert_eq!((results.total_items, results.answered, results.correct), (3, 1, 1));
+        assert_eq!(results.by_concept.len(), 1);
+        assert_eq!((results.by_domain[0].items, results.by_domain[0].correct), (3, 1));
+    }
+
+    #[test]
+    fn open_exam_items_are_listed_without_answers() {
+        let (db, session) = exam_with_items(2);
+        let extra = Item::new("extra".into(), ItemType::FreeRecall { correct_answer: "kept".into() }, vec![], "why".into());
+        db.create_item(&extra).unwrap();
+        let now = Utc::now();
+
+        let open = open_item_ids(&db, now).unwrap();
+        assert_eq!(open.len(), 2);
+        for item in withhold_open(&db, db.get_all_items().unwrap(), now).unwrap() {
+            let ItemType::FreeRecall { correct_answer } = &item.item_type else { unreachable!() };
+            assert_eq!(correct_answer.is_empty(), open.contains(&item.id));
+            assert_eq!(item.explanation.is_empty(), open.contains(&item.id));
+        }
+
+        assert!(open_item_ids(&db, session.deadline().unwrap() + Duration::seconds(1)).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,167 @@
+use crate::database::Database;
+use crate::exam;
+use crate::models::*;
+use crate::session_report;
+use crate::sir_scheduler::SirScheduler;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+
+/// Accuracy over the latest answers that ends a session when none is given.
+pub const DEFAULT_TARGET_ACCURACY: f64 = 0.8;
+/// Concept mastery (0-100) that ends a session when none is given.
+pub const DEFAULT_TARGET_MASTERY: f64 = 80.0;
+/// Answers the session must have, and the window its accuracy is taken over,
+/// before either target can end it.
+const RECENT_ANSWERS: usize = 5;
+/// Other items served before a missed item comes back.
+const REQUEUE_GAP: usize = 3;
+/// A miss is not re-queued once the item has been missed this many times.
+const MAX_MISSES: usize = 3;
+
+/// Starts a focused session after checking the concept and targets.
+pub fn start(db: &Database, session_type: SessionType, total_items: i32) -> Result<Session> {
+    let SessionType::Focused { concept_id, target_accuracy, target_mastery, .. } = &session_type else {
+        bail!("Session is not focused");
+    };
+    if db.get_concept(concept_id)?.is_none() {
+        bail!("Concept not found");
+    }
+    if target_accuracy.is_some_and(|t| !(0.0..=1.0).contains(&t)) {
+        bail!("Target accuracy must be between 0 and 1");
+    }
+    if target_mastery.is_some_and(|t| !(0.0..=100.0).contains(&t)) {
+        bail!("Target mastery must be between 0 and 100");
+    }
+
+    let session = Session::new(session_type, total_items);
+    db.create_session(&session)?;
+    Ok(session)
+}
+
+/// Picks the next item of a focused session. Missed items come back once
+/// `REQUEUE_GAP` other items have been answered, oldest miss first, ahead of
+/// unseen items; the concept's own items are served before those `scope` adds,
+/// easiest first. The session is completed, and `None` returned, when the
+/// latest answers reach the target accuracy, the concept reaches the target
+/// mastery, `total_items` answers are in (if set) or nothing is left to serve.
+pub fn next_item(
+    db: &Database,
+    sir: &SirScheduler,
+    mapping: &ConfidenceMapping,
+    session: &Session,
+    now: DateTime<Utc>,
+) -> Result<Option<Item>> {
+    if session.completed_at.is_some() {
+        return Ok(None);
+    }
+    let SessionType::Focused { concept_id, scope, target_accuracy, target_mastery } = &session.session_type else {
+        bail!("Session is not focused");
+    };
+
+    let attempts = db.get_attempts_by_session(&session.id)?;
+    let capped = session.total_items > 0 && attempts.len() as i32 >= session.total_items;
+    if capped
+        || reached_target(
+            db,
+            sir,
+            mapping,
+            concept_id,
+            &attempts,
+            target_accuracy.unwrap_or(DEFAULT_TARGET_ACCURACY),
+            target_mastery.unwrap_or(DEFAULT_TARGET_MASTERY),
+        )?
+    {
+        session_report::complete(db, session, now)?;
+        return Ok(None);
+    }
+
+    let pool = pool(db, concept_id, *scope, now)?;
+    match pick(&pool, &attempts) {
+        Some(item) => Ok(Some(item.clone())),
+        None => {
+            session_report::complete(db, session, now)?;
+            Ok(None)
+        }
+    }
+}
+
+fn reached_target(
+    db: &Database,
+    sir: &SirScheduler,
+    mapping: &ConfidenceMapping,
+    concept_id: &str,
+    attempts: &[Attempt],
+    target_accuracy: f64,
+    target_mastery: f64,
+) -> Result<bool> {
+    if attempts.len() < RECENT_ANSWERS {
+        return Ok(false);
+    }
+    let recent = &attempts[attempts.len() - RECENT_ANSWERS..];
+    let accuracy = recent.iter().filter(|a| a.is_correct).count() as f64 / recent.len() as f64;
+    if accuracy >= target_accuracy {
+        return Ok(true);
+    }
+
+    let filter = AttemptFilter { concept_id: Some(concept_id.to_string()), ..Default::default() };
+    let mut history = db.get_attempts_filtered(&filter)?;
+    history.reverse();
+    Ok(sir.calculate_mastery(&history, mapping) >= target_mastery)
+}
+
+/// The concept's items, then the items `scope` adds, each easiest first.
+/// Items drawn for a running exam are left out.
+fn pool(db: &Database, concept_id: &str, scope: FocusScope, now: DateTime<Utc>) -> Result<Vec<Item>> {
+    let Some(focus) = db.get_concept(concept_id)? else { bail!("Concept not found") };
+    let concepts: HashMap<String, Concept> = db.get_all_concepts()?.into_iter().map(|c| (c.id.clone(), c)).collect();
+    let in_exam = exam::open_item_ids(db, now)?;
+    let related = |item: &Item| {
+        item.concept_ids.iter().filter_map(|id| concepts.get(id)).any(|c| match scope {
+            FocusScope::Concept => false,
+            FocusScope::Tags => c.tags.iter().any(|tag| focus.tags.contains(tag)),
+            FocusScope::Domain => c.domain == focus.domain,
+        })
+    };
+
+    let (mut own, mut others): (Vec<Item>, Vec<Item>) = db
+        .get_all_items()?
+        .into_iter()
+        .filter(|item| !in_exam.contains(&item.id))
+        .filter(|item| item.concept_ids.contains(&focus.id) || related(item))
+        .partition(|item| item.concept_ids.contains(&focus.id));
+    for group in [&mut own, &mut others] {
+        group.sort_by(|a, b| a.difficulty.cmp(&b.difficulty).then_with(|| a.stem.cmp(&b.stem)));
+    }
+    own.extend(others);
+    Ok(own)
+}
+
+fn pick<'a>(pool: &'a [Item], attempts: &[Attempt]) -> Option<&'a Item> {
+    // Position and outcome of each item's latest answer, and its misses
+    let mut latest: HashMap<&str, (usize, bool)> = HashMap::new();
+    let mut misses: HashMap<&str, usize> = HashMap::new();
+    for (position, attempt) in attempts.iter().enumerate() {
+        latest.insert(attempt.item_id.as_str(), (position, attempt.is_correct));
+        if !attempt.is_correct {
+            *misses.entry(attempt.item_id.as_str()).or_default() += 1;
+        }
+    }
+
+    let mut retries: Vec<(usize, &Item)> = pool
+        .iter()
+        .filter_map(|item| match latest.get(item.id.as_str()) {
+            Some(&(position, false)) if misses[item.id.as_str()] < MAX_MISSES => Some((position, item)),
+            _ => None,
+        })
+        .collect();
+    retries.sort_by_key(|(position, _)| *position);
+
+    retries
+        .iter()
+        .find(|(position, _)| attempts.len() - position > REQUEUE_GAP)
+        .map(|(_, item)| *item)
+        .or_else(|| pool.iter().find(|item| !latest.contains_key(item.id.as_str())))
+        // Only misses still waiting out their gap are left
+        .or_else(|| retries.first().map(|(_, item)| *item))
+}
@@ -0,0 +1,407 @@
+use crate::grading::{within_tolerance, CALC_TOLERANCE};
+use crate::models::{CalcVariable, Item, ItemType};
+use rand::Rng;
+use std::collections::HashMap;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum FormulaError {
+    #[error("Invalid formula: {0}")]
+    Parse(String),
+    #[error("Unknown variable '{0}'")]
+    UnknownVariable(String),
+    #[error("Unknown function '{0}'")]
+    UnknownFunction(String),
+    #[error("Formula does not evaluate to a finite number")]
+    NotFinite,
+    #[error("Formula gives {computed}, but the correct answer is {expected}")]
+    Mismatch { computed: f64, expected: f64 },
+    #[error("Invalid range for '{name}': {reason}")]
+    InvalidRange { name: String, reason: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Op(char),
+    LParen,
+    RParen,
+    Comma,
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, FormulaError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            // Exponent, as in 1e-3 or 2.5E6
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                let digits_at = if matches!(chars.get(i + 1), Some('+' | '-')) { i + 2 } else { i + 1 };
+                if chars.get(digits_at).is_some_and(|c| c.is_ascii_digit()) {
+                    i = digits_at;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            let literal: String = chars[start..i].iter().collect();
+            let number = literal
+                .parse()
+                .map_err(|_| FormulaError::Parse(format!("bad number '{}'", literal)))?;
+            tokens.push(Token::Number(number));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else {
+            let token = match c {
+                '+' | '-' | '*' | '/' | '^' => Token::Op(c),
+                '×' => Token::Op('*'),
+                '÷' => Token::Op('/'),
+                '(' => Token::LParen,
+                ')' => Token::RParen,
+                ',' => Token::Comma,
+                _ => return Err(FormulaError::Parse(format!("unexpected '{}'", c))),
+            };
+            tokens.push(token);
+            i += 1;
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive-descent evaluator:
+/// expr := term (('+' | '-') term)*
+/// term := unary (('*' | '/') unary)*
+/// unary := ('-' | '+') unary | power
+/// power := primary ('^' unary)?
+/// primary := number | ident | ident '(' args ')' | '(' expr ')'
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    variables: &'a HashMap<String, f64>,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), FormulaError> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            other => Err(FormulaError::Parse(format!("expected {:?}, found {:?}", expected, other))),
+        }
+    }
+
+    fn expr(&mut self) -> Result<f64, FormulaError> {
+        let mut value = self.term()?;
+        while let Some(Token::Op(op @ ('+' | '-'))) = self.peek().cloned() {
+            self.pos += 1;
+            let rhs = self.term()?;
+            value = if op == '+' { value + rhs } else { value - rhs };
+        }
+        Ok(value)
+    }
+
+    fn term(&mut self) -> Result<f64, FormulaError> {
+        let mut value = self.unary()?;
+        while let Some(Token::Op(op @ ('*' | '/'))) = self.peek().cloned() {
+            self.pos += 1;
+            let rhs = self.unary()?;
+            value = if op == '*' { value * rhs } else { value / rhs };
+        }
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> Result<f64, FormulaError> {
+        match self.peek() {
+            Some(Token::Op('-')) => {
+                self.pos += 1;
+                Ok(-self.unary()?)
+            }
+            Some(Token::Op('+')) => {
+                self.pos += 1;
+                self.unary()
+            }
+            _ => self.power(),
+        }
+    }
+
+    fn power(&mut self) -> Result<f64, FormulaError> {
+        let base = self.primary()?;
+        if let Some(Token::Op('^')) = self.peek() {
+            self.pos += 1;
+            let exponent = self.unary()?;
+            return Ok(base.powf(exponent));
+        }
+        Ok(base)
+    }
+
+    fn primary(&mut self) -> Result<f64, FormulaError> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(n),
+            Some(Token::LParen) => {
+                let value = self.expr()?;
+                self.expect(Token::RParen)?;
+                Ok(value)
+            }
+            Some(Token::Ident(name)) => {
+                if let Some(Token::LParen) = self.peek() {
+                    self.pos += 1;
+                    let mut args = vec![self.expr()?];
+                    while let Some(Token::Comma) = self.peek() {
+                        self.pos += 1;
+                        args.push(self.expr()?);
+                    }
+                    self.expect(Token::RParen)?;
+                    Self::call(&name, &args)
+                } else {
+                    self.variables
+                        .get(&name)
+                        .copied()
+                        .ok_or(FormulaError::UnknownVariable(name))
+                }
+            }
+            other => Err(FormulaError::Parse(format!("unexpected {:?}", other))),
+        }
+    }
+
+    fn call(name: &str, args: &[f64]) -> Result<f64, FormulaError> {
+        let arity = |n: usize| {
+            if args.len() == n {
+                Ok(())
+            } else {
+                Err(FormulaError::Parse(format!("{} takes {} argument(s)", name, n)))
+            }
+        };
+
+        match name.to_lowercase().as_str() {
+            "sqrt" => arity(1).map(|_| args[0].sqrt()),
+            "ln" => arity(1).map(|_| args[0].ln()),
+            "log" | "log10" => arity(1).map(|_| args[0].log10()),
+            "exp" => arity(1).map(|_| args[0].exp()),
+            "abs" => arity(1).map(|_| args[0].abs()),
+            "min" => arity(2).map(|_| args[0].min(args[1])),
+            "max" => arity(2).map(|_| args[0].max(args[1])),
+            "pow" => arity(2).map(|_| args[0].powf(args[1])),
+            _ => Err(FormulaError::UnknownFunction(name.to_string())),
+        }
+    }
+}
+
+/// Evaluates a formula such as `CrCl = ((140 - age) * weight) / (72 * scr)`.
+/// Anything left of the last `=` is treated as a label.
+pub fn evaluate(formula: &str, variables: &HashMap<String, f64>) -> Result<f64, FormulaError> {
+    let expression = formula.rsplit('=').next().unwrap_or(formula);
+    let mut parser = Parser {
+        tokens: tokenize(expression)?,
+        pos: 0,
+        variables,
+    };
+
+    let value = parser.expr()?;
+    if parser.pos < parser.tokens.len() {
+        return Err(FormulaError::Parse(format!(
+            "unexpected {:?}",
+            parser.tokens[parser.pos]
+        )));
+    }
+    if !value.is_finite() {
+        return Err(FormulaError::NotFinite);
+    }
+    Ok(value)
+}
+
+fn variable_values(variables: &[CalcVariable]) -> HashMap<String, f64> {
+    variables.iter().map(|v| (v.name.clone(), v.value)).collect()
+}
+
+/// Checks that a calculation item's formula reproduces its `correct_answer`,
+/// and that every declared range is well formed and gives a finite result at
+/// both ends with the other variables at their current values. Other item
+/// types always pass.
+pub fn validate_item(item_type: &ItemType) -> Result<(), FormulaError> {
+    if let ItemType::Calculation { formula, variables, correct_answer, tolerance, .. } = item_type {
+        let values = variable_values(variables);
+        let computed = evaluate(formula, &values)?;
+        if !within_tolerance(*correct_answer, computed, tolerance.unwrap_or(CALC_TOLERANCE)) {
+            return Err(FormulaError::Mismatch { computed, expected: *correct_answer });
+        }
+
+        for variable in variables {
+            let invalid = |reason: &str| FormulaError::InvalidRange { name: variable.name.clone(), reason: reason.to_string() };
+            let (min, max) = match (variable.min, variable.max) {
+                (Some(min), Some(max)) => (min, max),
+                (None, None) => continue,
+                _ => return Err(invalid("both min and max are needed")),
+            };
+            if !(min.is_finite() && max.is_finite()) || min > max {
+                return Err(invalid("min must not exceed max"));
+            }
+            if variable.step.is_some_and(|step| !(step > 0.0 && step.is_finite())) {
+                return Err(invalid("step must be positive"));
+            }
+            for end in [min, max] {
+                let mut at_end = values.clone();
+                at_end.insert(variable.name.clone(), end);
+                match evaluate(formula, &at_end) {
+                    Err(FormulaError::NotFinite) => {
+                        return Err(invalid(&format!("formula is not finite at {}", end)));
+                    }
+                    other => other?,
+                };
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Draws fresh values for every variable with a declared `min`/`max` range and
+/// recomputes `correct_answer`. Returns false when the item has nothing to vary.
+/// On error the item is left unchanged.
+pub fn regenerate<R: Rng>(item: &mut Item, rng: &mut R) -> Result<bool, FormulaError> {
+    let ItemType::Calculation { formula, variables: current, correct_answer, .. } = &mut item.item_type else {
+        return Ok(false);
+    };
+
+    let mut variables = current.clone();
+    let mut changed = false;
+    for variable in variables.iter_mut() {
+        if let (Some(min), Some(max)) = (variable.min, variable.max) {
+            let step = variable.step.filter(|s| *s > 0.0).unwrap_or(1.0);
+            let steps = ((max - min) / step).floor().max(0.0) as i64;
+            let value = min + rng.gen_range(0..=steps) as f64 * step;
+            // Strip float noise from the step arithmetic
+            variable.value = (value * 1e6).round() / 1e6;
+            changed = true;
+        }
+    }
+
+    if changed {
+        *correct_answer = evaluate(formula, &variable_values(&variables))?;
+        *current = variables;
+    }
+    Ok(changed)
+}
+
+/// Replaces `{name}` placeholders in the stem and worked solution with the
+/// item's current variable values.
+pub fn render(item: &Item) -> Item {
+    let mut rendered = item.clone();
+    if let ItemType::Calculation { variables, worked_solution, .. } = &mut rendered.item_type {
+        let fill = |text: &str| {
+            variables.iter().fold(text.to_string(), |acc, v| {
+                acc.replace(&format!("{{{}}}", v.name), &v.value.to_string())
+            })
+        };
+        rendered.stem = fill(&rendered.stem);
+        *worked_solution = worked_solution.iter().map(|line| fill(line)).collect();
+    }
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(pairs: &[(&str, f64)]) -> HashMap<String, f64> {
+        pairs.iter().map(|(name, value)| (name.to_string(), *value)).collect()
+    }
+
+    fn dose_item(dose_min: f64, dose_max: f64, step: Option<f64>, formula: &str) -> ItemType {
+        ItemType::Calculation {
+            formula: formula.into(),
+            variables: vec![
+                CalcVariable { name: "dose".into(), value: 5.0, unit: "mg/kg".into(), min: Some(dose_min), max: Some(dose_max), step },
+                CalcVariable { name: "weight".into(), value: 70.0, unit: "kg".into(), min: None, max: None, step: None },
+            ],
+            correct_answer: evaluate(formula, &values(&[("dose", 5.0), ("weight", 70.0)])).unwrap_or(0.0),
+            unit: "mg".into(),
+            worked_solution: vec!["{dose} x {weight}".into()],
+            tolerance: None,
+        }
+    }
+
+    #[test]
+    fn evaluates_precedence_functions_and_exponents() {
+        let v = values(&[("age", 60.0), ("weight", 72.0), ("scr", 1.0)]);
+        assert_eq!(evaluate("CrCl = ((140 - age) * weight) / (72 * scr)", &v).unwrap(), 80.0);
+        assert_eq!(evaluate("2^3^2", &v).unwrap(), 512.0);
+        assert_eq!(evaluate("-2^2", &v).unwrap(), -4.0);
+        assert_eq!(evaluate("max(age, 2*weight)", &v).unwrap(), 144.0);
+        assert_eq!(evaluate("1e-3 * 1000", &v).unwrap(), 1.0);
+        assert_eq!(evaluate("2.5E2 + 1e+1", &v).unwrap(), 260.0);
+    }
+
+    #[test]
+    fn reports_bad_formulas() {
+        let v = values(&[("age", 60.0)]);
+        assert!(matches!(evaluate("age +", &v), Err(FormulaError::Parse(_))));
+        assert!(matches!(evaluate("(1+2", &v), Err(FormulaError::Parse(_))));
+        assert!(matches!(evaluate("foo * 2", &v), Err(FormulaError::UnknownVariable(_))));
+        assert!(matches!(evaluate("1/0", &v), Err(FormulaError::NotFinite)));
+    }
+
+    #[test]
+    fn validation_checks_the_answer_and_ranges() {
+        assert!(validate_item(&dose_item(2.0, 4.0, Some(0.5), "dose * weight")).is_ok());
+
+        let ItemType::Calculation { formula, variables, unit, worked_solution, tolerance, .. } =
+            dose_item(2.0, 4.0, None, "dose * weight")
+        else {
+            unreachable!()
+        };
+        let wrong = ItemType::Calculation { formula, variables, correct_answer: 300.0, unit, worked_solution, tolerance };
+        assert!(matches!(validate_item(&wrong), Err(FormulaError::Mismatch { .. })));
+
+        for bad in [
+            dose_item(4.0, 2.0, None, "dose * weight"),
+            dose_item(2.0, 4.0, Some(0.0), "dose * weight"),
+            dose_item(3.0, 6.0, None, "weight / (dose - 3)"),
+        ] {
+            assert!(matches!(validate_item(&bad), Err(FormulaError::InvalidRange { .. })));
+        }
+    }
+
+    #[test]
+    fn regenerate_draws_on_the_step_grid() {
+        let mut item = Item::new("Give {dose} mg/kg to {weight} kg".into(), dose_item(2.0, 4.0, Some(0.5), "dose * weight"), vec![], String::new());
+        for _ in 0..50 {
+            assert!(regenerate(&mut item, &mut rand::thread_rng()).unwrap());
+            let ItemType::Calculation { variables, correct_answer, .. } = &item.item_type else { unreachable!() };
+            let dose = variables[0].value;
+            assert!((2.0..=4.0).contains(&dose) && (dose * 2.0).fract() == 0.0);
+            assert_eq!(*correct_answer, dose * 70.0);
+        }
+        let rendered = render(&item);
+        assert!(!rendered.stem.contains('{') && rendered.stem.ends_with(" mg/kg to 70 kg"));
+    }
+
+    #[test]
+    fn failed_draw_leaves_the_item_unchanged() {
+        let mut item = Item::new(String::new(), dose_item(3.0, 3.0, None, "weight / (dose - 3)"), vec![], String::new());
+        let before = item.item_type.clone();
+        assert!(matches!(regenerate(&mut item, &mut rand::thread_rng()), Err(FormulaError::NotFinite)));
+        assert_eq!(serde_json::to_string(&item.item_type).unwrap(), serde_json::to_string(&before).unwrap());
+    }
+}
@@ -1,5 +1,7 @@
-use crate::models::{Attempt, ReviewState};
+use crate::models::{Attempt, ItemState, ReviewState};
+use serde::{Deserialize, Serialize};
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FSRSParameters {
     pub w: [f64; 17],
     pub request_retention: f64,
@@ -19,23 +21,43 @@ impl Default for FSRSParameters {
     }
 }
 
+#[derive(Default)]
 pub struct FSRSScheduler {
     params: FSRSParameters,
 }
 
-impl Default for FSRSScheduler {
-    fn default() -> Self {
-        Self {
-            params: FSRSParameters::default(),
-        }
-    }
-}
-
 impl FSRSScheduler {
     pub fn new(params: FSRSParameters) -> Self {
         Self { params }
     }
 
+    pub fn params(&self) -> &FSRSParameters {
+        &self.params
+    }
+
+    /// Maps a graded attempt to an FSRS rating (1 = Again ... 4 = Easy).
+    pub fn rating(is_correct: bool, confidence: i32) -> i32 {
+        if is_correct {
+            confidence.clamp(2, 4)
+        } else {
+            1
+        }
+    }
+
+    /// Probability of recall after `elapsed_days` at the given stability.
+    pub fn retrievability(&self, elapsed_days: i32, stability: f64) -> f64 {
+        self.forgetting_curve(elapsed_days, stability)
+    }
+
+    /// Seeds a new attempt with the memory state left by the item's previous
+    /// review, so `schedule` continues it instead of starting over.
+    pub fn resume(&self, attempt: &mut Attempt, state: &ItemState) {
+        attempt.stability = state.stability;
+        attempt.difficulty = state.difficulty;
+        attempt.review_state = state.review_state.clone();
+        attempt.elapsed_days = (attempt.attempted_at - state.last_attempted_at).num_days().max(0) as i32;
+    }
+
     pub fn schedule(&self, attempt: &mut Attempt, rating: i32) {
         match attempt.review_state {
             ReviewState::New => {
@@ -0,0 +1,189 @@
+use crate::fsrs::{FSRSParameters, FSRSScheduler};
+use crate::models::{Attempt, FsrsOptimizationReport, ReviewState};
+use anyhow::{bail, Result};
+
+/// Fewest scored reviews (a review with a prior review on an earlier day) worth fitting to.
+const MIN_REVIEWS: usize = 50;
+const ITERATIONS: usize = 200;
+const LEARNING_RATE: f64 = 0.02;
+const GRADIENT_STEP: f64 = 1e-4;
+/// Pull toward the starting weights so sparse histories do not overfit.
+const REGULARIZATION: f64 = 0.01;
+
+/// Allowed range for each weight, matching the published FSRS bounds.
+const BOUNDS: [(f64, f64); 17] = [
+    (0.1, 100.0),
+    (0.1, 100.0),
+    (0.1, 100.0),
+    (0.1, 100.0),
+    (1.0, 10.0),
+    (0.1, 5.0),
+    (0.1, 5.0),
+    (0.0, 0.75),
+    (0.0, 4.0),
+    (0.0, 0.8),
+    (0.01, 3.0),
+    (0.5, 5.0),
+    (0.01, 0.2),
+    (0.01, 0.9),
+    (0.01, 2.0),
+    (0.0, 1.0),
+    (1.0, 6.0),
+];
+
+/// One review: days since the previous review, FSRS rating, outcome.
+type Review = (i32, i32, bool);
+
+struct Metrics {
+    log_loss: f64,
+    rmse: f64,
+    count: usize,
+}
+
+/// Splits attempts into per-item review histories, oldest first.
+fn histories(attempts: &[Attempt]) -> Vec<Vec<Review>> {
+    let mut sorted: Vec<&Attempt> = attempts.iter().collect();
+    sorted.sort_by(|a, b| a.item_id.cmp(&b.item_id).then(a.attempted_at.cmp(&b.attempted_at)));
+
+    let mut histories: Vec<Vec<Review>> = Vec::new();
+    let mut previous: Option<&Attempt> = None;
+    for attempt in sorted {
+        let rating = FSRSScheduler::rating(attempt.is_correct, attempt.confidence);
+        match previous {
+            Some(prev) if prev.item_id == attempt.item_id => {
+                let elapsed = (attempt.attempted_at - prev.attempted_at).num_days().max(0) as i32;
+                histories.last_mut().unwrap().push((elapsed, rating, attempt.is_correct));
+            }
+            _ => histories.push(vec![(0, rating, attempt.is_correct)]),
+        }
+        previous = Some(attempt);
+    }
+    histories
+}
+
+/// Replays every history through the scheduler and scores the predicted
+/// retrievability against what actually happened. Same-day reviews update the
+/// memory state but are not scored.
+fn evaluate(params: &FSRSParameters, histories: &[Vec<Review>], scratch: &mut Attempt) -> Metrics {
+    let scheduler = FSRSScheduler::new(params.clone());
+    let mut log_loss = 0.0;
+    let mut squared_error = 0.0;
+    let mut count = 0;
+
+    for history in histories {
+        scratch.review_state = ReviewState::New;
+        scratch.stability = 0.0;
+        scratch.difficulty = 0.0;
+
+        for (i, &(elapsed, rating, is_correct)) in history.iter().enumerate() {
+            if i > 0 && elapsed > 0 {
+                let predicted = scheduler.retrievability(elapsed, scratch.stability).clamp(1e-4, 1.0 - 1e-4);
+                let actual = if is_correct { 1.0 } else { 0.0 };
+                log_loss -= actual * predicted.ln() + (1.0 - actual) * (1.0 - predicted).ln();
+                squared_error += (actual - predicted).powi(2);
+                count += 1;
+            }
+            scratch.elapsed_days = elapsed;
+            scheduler.schedule(scratch, rating);
+        }
+    }
+
+    let n = count.max(1) as f64;
+    Metrics {
+        log_loss: log_loss / n,
+        rmse: (squared_error / n).sqrt(),
+        count,
+    }
+}
+
+fn to_unit(w: &[f64; 17]) -> [f64; 17] {
+    let mut x = [0.0; 17];
+    for (i, (lo, hi)) in BOUNDS.iter().enumerate() {
+        x[i] = ((w[i] - lo) / (hi - lo)).clamp(0.0, 1.0);
+    }
+    x
+}
+
+fn from_unit(x: &[f64; 17], template: &FSRSParameters) -> FSRSParameters {
+    let mut params = template.clone();
+    for (i, (lo, hi)) in BOUNDS.iter().enumerate() {
+        params.w[i] = lo + x[i].clamp(0.0, 1.0) * (hi - lo);
+    }
+    params
+}
+
+/// Fits the 17 FSRS weights to the review history by minimising log loss with
+/// Adam over finite-difference gradients. Weights are searched in a [0, 1]
+/// space scaled to `BOUNDS`; retention target and maximum interval are kept.
+/// The report's `applied` flag is set only when the fit beats `start`.
+pub fn optimize(attempts: &[Attempt], start: &FSRSParameters) -> Result<(FSRSParameters, FsrsOptimizationReport)> {
+    let histories = histories(attempts);
+    let mut scratch = Attempt::new(String::new(), None, String::new(), false, 0, 0);
+
+    let before = evaluate(start, &histories, &mut scratch);
+    if before.count < MIN_REVIEWS {
+        bail!(
+            "Not enough review history to optimize: {} usable reviews, need {}",
+            before.count,
+            MIN_REVIEWS
+        );
+    }
+
+    let origin = to_unit(&start.w);
+    let mut objective = |x: &[f64; 17]| {
+        let penalty: f64 = x.iter().zip(origin.iter()).map(|(a, b)| (a - b).powi(2)).sum::<f64>() / 17.0;
+        evaluate(&from_unit(x, start), &histories, &mut scratch).log_loss + REGULARIZATION * penalty
+    };
+
+    let (beta1, beta2, epsilon) = (0.9, 0.999, 1e-8);
+    let mut x = origin;
+    let mut m = [0.0; 17];
+    let mut v = [0.0; 17];
+    let mut best_x = x;
+    let mut best_loss = objective(&x);
+
+    for t in 1..=ITERATIONS {
+        let base = objective(&x);
+        if base < best_loss {
+            best_loss = base;
+            best_x = x;
+        }
+
+        let mut gradient = [0.0; 17];
+        for (i, g) in gradient.iter_mut().enumerate() {
+            let mut probe = x;
+            probe[i] = (probe[i] + GRADIENT_STEP).min(1.0);
+            let step = probe[i] - x[i];
+            if step > 0.0 {
+                *g = (objective(&probe) - base) / step;
+            }
+        }
+
+        for i in 0..17 {
+            m[i] = beta1 * m[i] + (1.0 - beta1) * gradient[i];
+            v[i] = beta2 * v[i] + (1.0 - beta2) * gradient[i] * gradient[i];
+            let m_hat = m[i] / (1.0 - beta1.powi(t as i32));
+            let v_hat = v[i] / (1.0 - beta2.powi(t as i32));
+            x[i] = (x[i] - LEARNING_RATE * m_hat / (v_hat.sqrt() + epsilon)).clamp(0.0, 1.0);
+        }
+    }
+    if objective(&x) < best_loss {
+        best_x = x;
+    }
+
+    let fitted = from_unit(&best_x, start);
+    let after = evaluate(&fitted, &histories, &mut scratch);
+    let applied = after.log_loss < before.log_loss;
+    let params = if applied { fitted } else { start.clone() };
+
+    let report = FsrsOptimizationReport {
+        reviews_used: before.count as i32,
+        log_loss_before: before.log_loss,
+        log_loss_after: after.log_loss,
+        rmse_before: before.rmse,
+        rmse_after: after.rmse,
+        weights: params.w.to_vec(),
+        applied,
+    };
+    Ok((params, report))
+}
@@ -0,0 +1,313 @@
+use crate::models::{AnswerError, GradeResult, Item, ItemAnswer, ItemType, PartFeedback};
+use crate::units::{self, Unit, UnitError};
+use anyhow::{bail, Result};
+
+/// Minimum score (0-1) for a partially-credited answer to count as correct.
+const PASS_SCORE: f64 = 0.8;
+
+/// Default relative tolerance for numeric answers.
+pub const CALC_TOLERANCE: f64 = 0.01;
+
+/// Grades a structured answer against the item's answer key.
+pub fn grade(item: &Item, answer: &ItemAnswer) -> Result<GradeResult> {
+    match (&item.item_type, answer) {
+        (ItemType::Mcq { options }, ItemAnswer::Mcq { option_id }) => {
+            let chosen = match options.iter().find(|o| &o.id == option_id) {
+                Some(option) => option,
+                None => bail!("Option {} does not belong to item {}", option_id, item.id),
+            };
+            let keyed: Vec<&str> = options.iter().filter(|o| o.is_correct).map(|o| o.id.as_str()).collect();
+            let feedback = PartFeedback {
+                part_id: chosen.id.clone(),
+                is_correct: chosen.is_correct,
+                points_awarded: if chosen.is_correct { 1.0 } else { 0.0 },
+                points_possible: 1.0,
+                expected: keyed.join(", "),
+                message: chosen.explanation.clone(),
+                error: None,
+            };
+            Ok(from_parts(vec![feedback], true))
+        }
+        (ItemType::FreeRecall { correct_answer }, ItemAnswer::FreeRecall { text }) => {
+            let score = text_score(correct_answer, text);
+            let feedback = PartFeedback {
+                part_id: "answer".to_string(),
+                is_correct: score >= PASS_SCORE,
+                points_awarded: score,
+                points_possible: 1.0,
+                expected: correct_answer.clone(),
+                message: None,
+                error: None,
+            };
+            Ok(from_parts(vec![feedback], false))
+        }
+        (
+            ItemType::Calculation { correct_answer, unit, tolerance, .. },
+            ItemAnswer::Calculation { value, unit: given_unit },
+        ) => {
+            let tolerance = tolerance.unwrap_or(CALC_TOLERANCE);
+            let error = check_calculation(*correct_answer, unit, *value, given_unit, tolerance);
+            let is_correct = error.is_none();
+            let message = error.as_ref().map(|e| {
+                match e {
+                    AnswerError::WrongUnit => "Correct value, wrong unit",
+                    AnswerError::IncompatibleUnit => "Unit does not measure the requested quantity",
+                    AnswerError::UnknownUnit => "Unrecognized unit",
+                    AnswerError::OutOfTolerance => "Value outside tolerance",
+                }
+                .to_string()
+            });
+            let feedback = PartFeedback {
+                part_id: "answer".to_string(),
+                is_correct,
+                points_awarded: if is_correct { 1.0 } else { 0.0 },
+                points_possible: 1.0,
+                expected: format!("{} {}", correct_answer, unit).trim().to_string(),
+                message,
+                error,
+            };
+            Ok(from_parts(vec![feedback], true))
+        }
+        (ItemType::CaseVignette { steps }, ItemAnswer::CaseVignette { steps: answers }) => {
+            let feedback = steps
+                .iter()
+                .map(|step| {
+                    let given = answers
+                        .iter()
+                        .find(|a| a.step_number == step.step_number)
+                        .map(|a| a.answer.as_str())
+                        .unwrap_or("");
+                    let is_correct = matches_text(&step.correct_answer, given);
+                    PartFeedback {
+                        part_id: step.step_number.to_string(),
+                        is_correct,
+                        points_awarded: if is_correct { step.points as f64 } else { 0.0 },
+                        points_possible: step.points as f64,
+                        expected: step.correct_answer.clone(),
+                        message: Some(step.explanation.clone()),
+                        error: None,
+                    }
+                })
+                .collect();
+            Ok(from_parts(feedback, false))
+        }
+        (ItemType::Cloze { blanks }, ItemAnswer::Cloze { blanks: answers }) => {
+            let feedback = blanks
+                .iter()
+                .map(|blank| {
+                    let given = answers.get(&blank.id).map(String::as_str).unwrap_or("");
+                    let is_correct = matches_text(&blank.correct_answer, given);
+                    PartFeedback {
+                        part_id: blank.id.clone(),
+                        is_correct,
+                        points_awarded: if is_correct { 1.0 } else { 0.0 },
+                        points_possible: 1.0,
+                        expected: blank.correct_answer.clone(),
+                        message: None,
+                        error: None,
+                    }
+                })
+                .collect();
+            Ok(from_parts(feedback, true))
+        }
+        _ => bail!("Answer type does not match the type of item {}", item.id),
+    }
+}
+
+/// Flattens a structured answer into the text stored in `Attempt.user_answer`.
+/// MCQ answers are stored as the chosen option id.
+pub fn answer_text(answer: &ItemAnswer) -> String {
+    match answer {
+        ItemAnswer::Mcq { option_id } => option_id.clone(),
+        ItemAnswer::FreeRecall { text } => text.clone(),
+        ItemAnswer::Calculation { value, unit } => format!("{} {}", value, unit).trim().to_string(),
+        ItemAnswer::CaseVignette { .. } | ItemAnswer::Cloze { .. } => {
+            serde_json::to_string(answer).unwrap_or_default()
+        }
+    }
+}
+
+/// Builds the overall result. With `all_or_nothing` every part must be right;
+/// otherwise the item passes once the points-weighted score reaches `PASS_SCORE`.
+fn from_parts(feedback: Vec<PartFeedback>, all_or_nothing: bool) -> GradeResult {
+    let possible: f64 = feedback.iter().map(|f| f.points_possible).sum();
+    let awarded: f64 = feedback.iter().map(|f| f.points_awarded).sum();
+    let score = if possible > 0.0 {
+        awarded / possible
+    } else if feedback.is_empty() {
+        0.0
+    } else {
+        feedback.iter().filter(|f| f.is_correct).count() as f64 / feedback.len() as f64
+    };
+
+    let is_correct = if all_or_nothing {
+        !feedback.is_empty() && feedback.iter().all(|f| f.is_correct)
+    } else {
+        score >= PASS_SCORE
+    };
+
+    GradeResult { is_correct, score, feedback }
+}
+
+/// Converts the answer into the item's unit and compares within `tolerance`.
+/// A blank answer unit is read as the item's unit. Returns `None` when correct.
+fn check_calculation(
+    expected: f64,
+    expected_unit: &str,
+    value: f64,
+    given_unit: &str,
+    tolerance: f64,
+) -> Option<AnswerError> {
+    let given_unit = if given_unit.trim().is_empty() { expected_unit } else { given_unit };
+    let raw_matches = within_tolerance(expected, value, tolerance);
+
+    // Free-text item units we cannot parse (e.g. "tablets") are compared literally
+    if Unit::parse(expected_unit).is_err() && normalize(given_unit) == normalize(expected_unit) {
+        return if raw_matches { None } else { Some(AnswerError::OutOfTolerance) };
+    }
+
+    match units::convert(value, given_unit, expected_unit) {
+        Ok(converted) if within_tolerance(expected, converted, tolerance) => None,
+        Ok(_) if raw_matches => Some(AnswerError::WrongUnit),
+        Ok(_) => Some(AnswerError::OutOfTolerance),
+        Err(_) if raw_matches => Some(AnswerError::WrongUnit),
+        Err(UnitError::Incompatible { .. }) => Some(AnswerError::IncompatibleUnit),
+        Err(UnitError::Unknown(_)) => Some(AnswerError::UnknownUnit),
+    }
+}
+
+pub fn within_tolerance(expected: f64, given: f64, tolerance: f64) -> bool {
+    if expected == 0.0 {
+        given.abs() <= tolerance
+    } else {
+        ((given - expected) / expected).abs() <= tolerance
+    }
+}
+
+/// Lowercases and strips punctuation so "Beta-blocker." matches "beta blocker".
+fn normalize(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Exact match after normalization. The key may list alternatives separated by `|`.
+fn matches_text(expected: &str, given: &str) -> bool {
+    let given = normalize(given);
+    !given.is_empty() && expected.split('|').any(|alt| normalize(alt) == given)
+}
+
+/// Words that flip the meaning of an otherwise matching answer.
+const NEGATIONS: [&str; 8] = ["not", "no", "never", "non", "cannot", "isn", "doesn", "without"];
+
+/// 1.0 for a normalized match, otherwise the token F1 against the best
+/// alternative, so extra words cost as much as missing ones. An answer that
+/// negates a key without negations scores 0.
+fn text_score(expected: &str, given: &str) -> f64 {
+    if matches_text(expected, given) {
+        return 1.0;
+    }
+
+    let given = normalize(given);
+    let given_words: Vec<&str> = given.split(' ').filter(|w| !w.is_empty()).collect();
+    expected
+        .split('|')
+        .map(|alt| {
+            let key = normalize(alt);
+            let key_words: Vec<&str> = key.split(' ').filter(|w| !w.is_empty()).collect();
+            if key_words.is_empty() || given_words.is_empty() {
+                return 0.0;
+            }
+            let negated = |words: &[&str]| words.iter().any(|w| NEGATIONS.contains(w));
+            if negated(&given_words) != negated(&key_words) {
+                return 0.0;
+            }
+
+            // Each key word can be matched once
+            let mut unmatched = key_words.clone();
+            let hits = given_words
+                .iter()
+                .filter(|w| match unmatched.iter().position(|k| k == *w) {
+                    Some(i) => {
+                        unmatched.swap_remove(i);
+                        true
+                    }
+                    None => false,
+                })
+                .count() as f64;
+            if hits == 0.0 {
+                return 0.0;
+            }
+            let precision = hits / given_words.len() as f64;
+            let recall = hits / key_words.len() as f64;
+            2.0 * precision * recall / (precision + recall)
+        })
+        .fold(0.0, f64::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn free_recall_needs_the_key_words_and_little_else() {
+        assert_eq!(text_score("Loop diuretic", "loop-diuretic."), 1.0);
+        assert_eq!(text_score("beta blocker|propranolol", "Propranolol"), 1.0);
+        assert!(text_score("loop diuretic", "a loop diuretic") >= PASS_SCORE);
+        assert!(text_score("loop diuretic", "loop") < PASS_SCORE);
+    }
+
+    #[test]
+    fn free_recall_rejects_word_dumps_and_negations() {
+        let dump = "thiazide loop potassium sparing osmotic diuretic carbonic anhydrase";
+        assert!(text_score("loop diuretic", dump) < PASS_SCORE);
+        assert_eq!(text_score("loop diuretic", "not a loop diuretic"), 0.0);
+        assert_eq!(text_score("loop diuretic", "isn't a loop diuretic"), 0.0);
+        assert!(text_score("not contraindicated", "not contraindicated here") >= PASS_SCORE);
+    }
+
+    fn calculation(correct_answer: f64, unit: &str) -> Item {
+        let item_type = ItemType::Calculation {
+            formula: String::new(),
+            variables: vec![],
+            correct_answer,
+            unit: unit.into(),
+            worked_solution: vec![],
+            tolerance: None,
+        };
+        Item::new("stem".into(), item_type, vec![], String::new())
+    }
+
+    fn error_for(item: &Item, value: f64, unit: &str) -> Option<AnswerError> {
+        let answer = ItemAnswer::Calculation { value, unit: unit.into() };
+        grade(item, &answer).unwrap().feedback[0].error.clone()
+    }
+
+    #[test]
+    fn calculation_accepts_equivalent_units_within_tolerance() {
+        let item = calculation(500.0, "mg");
+        assert_eq!(error_for(&item, 0.5, "g"), None);
+        assert_eq!(error_for(&item, 504.0, "mg"), None);
+        // A bare number is read in the key's unit
+        assert_eq!(error_for(&item, 501.0, ""), None);
+        assert_eq!(error_for(&calculation(3.0, "tabs"), 3.0, "Tabs"), None);
+    }
+
+    #[test]
+    fn calculation_separates_unit_errors_from_wrong_values() {
+        let item = calculation(500.0, "mg");
+        assert_eq!(error_for(&item, 500.0, "g"), Some(AnswerError::WrongUnit));
+        assert_eq!(error_for(&item, 500.0, "mL"), Some(AnswerError::WrongUnit));
+        assert_eq!(error_for(&item, 5.0, "mL"), Some(AnswerError::IncompatibleUnit));
+        assert_eq!(error_for(&item, 520.0, "mg"), Some(AnswerError::OutOfTolerance));
+        assert_eq!(error_for(&item, 5.0, "furlongs"), Some(AnswerError::UnknownUnit));
+
+        let graded = grade(&item, &ItemAnswer::Calculation { value: 500.0, unit: "g".into() }).unwrap();
+        assert!(!graded.is_correct);
+        assert_eq!(graded.feedback[0].message.as_deref(), Some("Correct value, wrong unit"));
+    }
+}
@@ -0,0 +1,186 @@
+use crate::models::{Attempt, IrtCalibrationReport, IrtModel, Item, ItemCalibration};
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+
+/// Fewest responses an item needs before its parameters are refitted.
+pub const MIN_RESPONSES: usize = 10;
+/// Discrimination below this marks an item that barely tells strong and weak
+/// answers apart; below zero, stronger occasions do worse on it.
+pub const LOW_DISCRIMINATION: f64 = 0.2;
+const ITERATIONS: usize = 100;
+/// Largest change to any parameter in one iteration, in logits.
+const MAX_STEP: f64 = 1.0;
+/// Priors keep items answered all right or all wrong finite.
+const DIFFICULTY_PRIOR_SD: f64 = 3.0;
+const DISCRIMINATION_PRIOR_SD: f64 = 1.0;
+const MAX_DISCRIMINATION: f64 = 4.0;
+/// Ability quadrature: a standard normal prior over -4..4 logits.
+const GRID_LIMIT: f64 = 4.0;
+const GRID_POINTS: usize = 41;
+/// `Item.difficulty` 0 and 100 sit this many logits either side of average.
+const DIFFICULTY_RANGE: f64 = 3.0;
+
+/// Maps `Item.difficulty` (0-100, 50 = average) onto the logit scale, -3..3.
+pub fn difficulty_logit(difficulty: i32) -> f64 {
+    (difficulty.clamp(0, 100) as f64 - 50.0) / 50.0 * DIFFICULTY_RANGE
+}
+
+/// Inverse of `difficulty_logit`; logits beyond -3..3 clamp to 0 or 100.
+pub fn difficulty_score(logit: f64) -> i32 {
+    (logit / DIFFICULTY_RANGE * 50.0 + 50.0).round().clamp(0.0, 100.0) as i32
+}
+
+/// 2PL probability of a correct answer; a discrimination of 1 is the Rasch model.
+pub fn probability(ability: f64, difficulty: f64, discrimination: f64) -> f64 {
+    1.0 / (1.0 + (-discrimination * (ability - difficulty)).exp())
+}
+
+/// `(item id, difficulty, discrimination)` to write back to an item.
+pub type Calibration = (String, i32, f64);
+
+/// Fits item parameters to the attempt history by marginal maximum a
+/// posteriori estimation: EM over an ability grid, with a Fisher scoring step
+/// per item parameter in each M step. There is a single learner, so each
+/// session (or each day, for attempts made outside a session) is treated as a
+/// separate test taker whose ability is integrated out. Items with fewer than
+/// `MIN_RESPONSES` responses are left out of the fit and unchanged. Under 1PL
+/// discrimination is held at 1 for fitting and the stored value is kept.
+pub fn calibrate(attempts: &[Attempt], items: &[Item], model: IrtModel) -> Result<(Vec<Calibration>, IrtCalibrationReport)> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for attempt in attempts {
+        *counts.entry(attempt.item_id.as_str()).or_default() += 1;
+    }
+    let attempted = items.iter().filter(|item| counts.contains_key(item.id.as_str())).count();
+    let fitted: Vec<&Item> = items
+        .iter()
+        .filter(|item| counts.get(item.id.as_str()).is_some_and(|&n| n >= MIN_RESPONSES))
+        .collect();
+    if fitted.is_empty() {
+        bail!("Not enough responses to calibrate: no item has {} or more attempts", MIN_RESPONSES);
+    }
+    let item_index: HashMap<&str, usize> = fitted.iter().enumerate().map(|(i, item)| (item.id.as_str(), i)).collect();
+
+    // (item index, correct) responses per occasion
+    let mut occasion_index: HashMap<String, usize> = HashMap::new();
+    let mut occasions: Vec<Vec<(usize, bool)>> = Vec::new();
+    for attempt in attempts {
+        let Some(&item) = item_index.get(attempt.item_id.as_str()) else { continue };
+        let key = attempt
+            .session_id
+            .clone()
+            .unwrap_or_else(|| attempt.attempted_at.format("%Y-%m-%d").to_string());
+        let next = occasion_index.len();
+        let occasion = *occasion_index.entry(key).or_insert(next);
+        if occasion == occasions.len() {
+            occasions.push(Vec::new());
+        }
+        occasions[occasion].push((item, attempt.is_correct));
+    }
+
+    let step = 2.0 * GRID_LIMIT / (GRID_POINTS - 1) as f64;
+    let grid: Vec<f64> = (0..GRID_POINTS).map(|k| -GRID_LIMIT + k as f64 * step).collect();
+    let prior: Vec<f64> = grid.iter().map(|t| -0.5 * t * t).collect();
+
+    let mut difficulty = vec![0.0; fitted.len()];
+    let mut discrimination = vec![1.0; fitted.len()];
+    for _ in 0..ITERATIONS {
+        // E step: expected responses and correct responses per item at each grid point
+        let mut expected = vec![vec![0.0; GRID_POINTS]; fitted.len()];
+        let mut expected_correct = vec![vec![0.0; GRID_POINTS]; fitted.len()];
+        for responses in &occasions {
+            let log_density: Vec<f64> = grid
+                .iter()
+                .zip(&prior)
+                .map(|(&theta, &prior)| {
+                    prior
+                        + responses
+                            .iter()
+                            .map(|&(i, correct)| {
+                                let p = probability(theta, difficulty[i], discrimination[i]);
+                                if correct { p.ln() } else { (1.0 - p).ln() }
+                            })
+                            .sum::<f64>()
+                })
+                .collect();
+            let max = log_density.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            let weights: Vec<f64> = log_density.iter().map(|l| (l - max).exp()).collect();
+            let total: f64 = weights.iter().sum();
+            for &(i, correct) in responses {
+                for (k, w) in weights.iter().enumerate() {
+                    expected[i][k] += w / total;
+                    if correct {
+                        expected_correct[i][k] += w / total;
+                    }
+                }
+            }
+        }
+
+        // M step, with N(0, 3^2) and N(1, 1) priors on difficulty and discrimination
+        for i in 0..fitted.len() {
+            let (a, b) = (discrimination[i], difficulty[i]);
+            let mut b_gradient = -b / DIFFICULTY_PRIOR_SD.powi(2);
+            let mut b_information = DIFFICULTY_PRIOR_SD.powi(-2);
+            let mut a_gradient = -(a - 1.0) / DISCRIMINATION_PRIOR_SD.powi(2);
+            let mut a_information = DISCRIMINATION_PRIOR_SD.powi(-2);
+            for (k, theta) in grid.iter().enumerate() {
+                let p = probability(*theta, b, a);
+                let residual = expected_correct[i][k] - expected[i][k] * p;
+                let variance = expected[i][k] * p * (1.0 - p);
+                b_gradient -= a * residual;
+                b_information += a * a * variance;
+                a_gradient += (theta - b) * residual;
+                a_information += (theta - b).powi(2) * variance;
+            }
+            difficulty[i] += (b_gradient / b_information).clamp(-MAX_STEP, MAX_STEP);
+            if model == IrtModel::TwoPl {
+                discrimination[i] = (a + (a_gradient / a_information).clamp(-MAX_STEP, MAX_STEP))
+                    .clamp(-MAX_DISCRIMINATION, MAX_DISCRIMINATION);
+            }
+        }
+    }
+
+    let mut calibrations = Vec::with_capacity(fitted.len());
+    let mut rows = Vec::with_capacity(fitted.len());
+    for (i, item) in fitted.iter().enumerate() {
+        let outcomes: Vec<bool> = occasions.iter().flatten().filter(|r| r.0 == i).map(|r| r.1).collect();
+        let new_discrimination = match model {
+            IrtModel::OnePl => item.discrimination,
+            IrtModel::TwoPl => discrimination[i],
+        };
+        let flag = match model {
+            IrtModel::TwoPl if new_discrimination < 0.0 => Some("negative_discrimination".to_string()),
+            IrtModel::TwoPl if new_discrimination < LOW_DISCRIMINATION => Some("low_discrimination".to_string()),
+            _ => None,
+        };
+        let new_difficulty = difficulty_score(difficulty[i]);
+        calibrations.push((item.id.clone(), new_difficulty, new_discrimination));
+        rows.push(ItemCalibration {
+            item_id: item.id.clone(),
+            stem: item.stem.clone(),
+            responses: outcomes.len() as i32,
+            proportion_correct: outcomes.iter().filter(|c| **c).count() as f64 / outcomes.len() as f64,
+            difficulty_before: item.difficulty,
+            difficulty: new_difficulty,
+            discrimination_before: item.discrimination,
+            discrimination: new_discrimination,
+            flag,
+        });
+    }
+    rows.sort_by(|a, b| {
+        a.flag
+            .is_none()
+            .cmp(&b.flag.is_none())
+            .then(a.discrimination.total_cmp(&b.discrimination))
+            .then_with(|| a.stem.cmp(&b.stem))
+    });
+
+    let report = IrtCalibrationReport {
+        model,
+        responses_used: occasions.iter().map(Vec::len).sum::<usize>() as i32,
+        occasions: occasions.len() as i32,
+        flagged: rows.iter().filter(|r| r.flag.is_some()).count() as i32,
+        skipped: (attempted - fitted.len()) as i32,
+        items: rows,
+    };
+    Ok((calibrations, report))
+}
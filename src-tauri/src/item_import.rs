@@ -0,0 +1,246 @@
+use crate::database::Database;
+use crate::models::*;
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+
+/// Picks tab or comma, whichever the header line uses more.
+fn detect_delimiter(content: &str) -> u8 {
+    let header = content.lines().next().unwrap_or("");
+    if header.matches('\t').count() > header.matches(',').count() {
+        b'\t'
+    } else {
+        b','
+    }
+}
+
+/// Column indexes resolved from the mapping's header names.
+struct Columns {
+    stem: usize,
+    correct_answer: usize,
+    item_type: Option<usize>,
+    options: Option<usize>,
+    explanation: Option<usize>,
+    difficulty: Option<usize>,
+    source: Option<usize>,
+    concepts: Option<usize>,
+}
+
+impl Columns {
+    fn resolve(headers: &csv::StringRecord, mapping: &ItemColumnMapping) -> Result<Self> {
+        let index: HashMap<String, usize> = headers
+            .iter()
+            .enumerate()
+            .map(|(i, h)| (h.trim().to_lowercase(), i))
+            .collect();
+        let find = |name: &str| {
+            index
+                .get(&name.trim().to_lowercase())
+                .copied()
+                .with_context(|| format!("Column '{}' not found in the header row", name))
+        };
+        let optional = |name: &Option<String>| name.as_deref().map(find).transpose();
+
+        Ok(Self {
+            stem: find(&mapping.stem)?,
+            correct_answer: find(&mapping.correct_answer)?,
+            item_type: optional(&mapping.item_type)?,
+            options: optional(&mapping.options)?,
+            explanation: optional(&mapping.explanation)?,
+            difficulty: optional(&mapping.difficulty)?,
+            source: optional(&mapping.source)?,
+            concepts: optional(&mapping.concepts)?,
+        })
+    }
+}
+
+/// Turns inline `{{answer}}` markers into `{{}}` blanks.
+fn parse_blanks(text: &str) -> (String, Vec<ClozeBlanks>) {
+    let mut stem = String::new();
+    let mut blanks = Vec::new();
+    let mut rest = text;
+    while let (Some(start), Some(end)) = (rest.find("{{"), rest.find("}}")) {
+        if end < start {
+            break;
+        }
+        stem.push_str(&rest[..start]);
+        stem.push_str("{{}}");
+        blanks.push(ClozeBlanks {
+            id: format!("c{}", blanks.len() + 1),
+            correct_answer: rest[start + 2..end].trim().to_string(),
+        });
+        rest = &rest[end + 2..];
+    }
+    stem.push_str(rest);
+    (stem, blanks)
+}
+
+/// Builds MCQ options from `A|B|C`. Each key in `correct` may be the option
+/// text, its letter or its 1-based number; several keys are separated by `|`.
+fn parse_options(options: &str, correct: &str) -> Result<Vec<McqOption>, String> {
+    let texts: Vec<&str> = options.split('|').map(str::trim).filter(|o| !o.is_empty()).collect();
+    if texts.len() < 2 {
+        return Err("MCQ needs at least two options separated by '|'".to_string());
+    }
+
+    let mut options: Vec<McqOption> = texts
+        .iter()
+        .enumerate()
+        .map(|(i, text)| McqOption {
+            id: ((b'a' + i as u8) as char).to_string(),
+            text: text.to_string(),
+            is_correct: false,
+            explanation: None,
+        })
+        .collect();
+
+    for key in correct.split('|').map(str::trim).filter(|k| !k.is_empty()) {
+        let position = options
+            .iter()
+            .position(|o| o.text.eq_ignore_ascii_case(key) || o.id.eq_ignore_ascii_case(key))
+            .or_else(|| key.parse::<usize>().ok().filter(|n| (1..=options.len()).contains(n)).map(|n| n - 1));
+        match position {
+            Some(i) => options[i].is_correct = true,
+            None => return Err(format!("'{}' does not match any option", key)),
+        }
+    }
+    if !options.iter().any(|o| o.is_correct) {
+        return Err("MCQ has no correct option".to_string());
+    }
+    Ok(options)
+}
+
+/// Validates every row and, unless `dry_run` is set or any row failed, creates
+/// all items plus any concepts they name in a single transaction. Concept
+/// names match existing concepts case-insensitively, preferring
+/// `default_domain`; unknown names become new concepts in that domain.
+pub fn import_items(
+    db: &Database,
+    content: &str,
+    mapping: &ItemColumnMapping,
+    default_domain: &str,
+    dry_run: bool,
+) -> Result<ItemImportReport> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(detect_delimiter(content))
+        .flexible(true)
+        .from_reader(content.as_bytes());
+    let headers = reader.headers().context("Missing header row")?.clone();
+    let columns = Columns::resolve(&headers, mapping)?;
+    let header_name = |i: usize| headers.get(i).unwrap_or("").to_string();
+
+    let mut existing: HashMap<String, Concept> = HashMap::new();
+    for concept in db.get_all_concepts()? {
+        let key = concept.name.to_lowercase();
+        let replace = match existing.get(&key) {
+            Some(current) => current.domain != default_domain && concept.domain == default_domain,
+            None => true,
+        };
+        if replace {
+            existing.insert(key, concept);
+        }
+    }
+
+    let mut report = ItemImportReport { dry_run, ..Default::default() };
+    let mut data = CollectionData::default();
+
+    for record in reader.records() {
+        let record = record?;
+        let row = record.position().map(|p| p.line() as usize).unwrap_or(0);
+        if record.iter().all(|field| field.trim().is_empty()) {
+            continue;
+        }
+        report.rows += 1;
+
+        let get = |i: Option<usize>| i.and_then(|i| record.get(i)).map(str::trim).unwrap_or("");
+        let mut errors: Vec<ImportRowError> = Vec::new();
+        let mut fail = |column: Option<usize>, message: String| {
+            errors.push(ImportRowError { row, column: column.map(header_name), message });
+        };
+
+        let stem = get(Some(columns.stem));
+        let correct = get(Some(columns.correct_answer));
+        if stem.is_empty() {
+            fail(Some(columns.stem), "Stem is empty".to_string());
+        }
+
+        let kind = get(columns.item_type).to_lowercase().replace(['_', ' '], "-");
+        let parsed = match kind.as_str() {
+            "" | "free-recall" | "freerecall" | "basic" => {
+                if correct.is_empty() {
+                    fail(Some(columns.correct_answer), "Correct answer is empty".to_string());
+                }
+                Some((stem.to_string(), ItemType::FreeRecall { correct_answer: correct.to_string() }))
+            }
+            "mcq" | "multiple-choice" => match parse_options(get(columns.options), correct) {
+                Ok(options) => Some((stem.to_string(), ItemType::Mcq { options })),
+                Err(message) => {
+                    fail(columns.options.or(Some(columns.correct_answer)), message);
+                    None
+                }
+            },
+            "cloze" => {
+                let (cloze_stem, blanks) = parse_blanks(stem);
+                if blanks.is_empty() || blanks.iter().any(|b| b.correct_answer.is_empty()) {
+                    fail(Some(columns.stem), "Cloze stem needs {{answer}} blanks".to_string());
+                }
+                Some((cloze_stem, ItemType::Cloze { blanks }))
+            }
+            other => {
+                fail(columns.item_type, format!("Unsupported item type '{}'", other));
+                None
+            }
+        };
+
+        let difficulty = match get(columns.difficulty) {
+            "" => None,
+            raw => match raw.parse::<i32>() {
+                Ok(value) if (0..=100).contains(&value) => Some(value),
+                _ => {
+                    fail(columns.difficulty, format!("Difficulty '{}' is not a whole number from 0 to 100", raw));
+                    None
+                }
+            },
+        };
+
+        if !errors.is_empty() {
+            report.errors.extend(errors);
+            continue;
+        }
+        let Some((stem, item_type)) = parsed else { continue };
+        report.valid_rows += 1;
+
+        let mut concept_ids: Vec<String> = Vec::new();
+        for name in get(columns.concepts).split(';').map(str::trim).filter(|n| !n.is_empty()) {
+            let concept = existing.entry(name.to_lowercase()).or_insert_with(|| {
+                report.new_concepts.push(name.to_string());
+                let concept = Concept::new(name.to_string(), default_domain.to_string());
+                data.concepts.push(concept.clone());
+                concept
+            });
+            if !concept_ids.contains(&concept.id) {
+                concept_ids.push(concept.id.clone());
+            }
+        }
+
+        let mut item = Item::new(stem, item_type, concept_ids, get(columns.explanation).to_string());
+        if let Some(difficulty) = difficulty {
+            item.difficulty = difficulty;
+        }
+        let source = get(columns.source);
+        if !source.is_empty() {
+            item.source = Some(source.to_string());
+        }
+        data.items.push(item);
+    }
+
+    if report.rows == 0 {
+        bail!("No rows to import");
+    }
+    if dry_run || !report.errors.is_empty() {
+        return Ok(report);
+    }
+
+    db.restore_collection(&data, RestoreMode::Merge)?;
+    report.items_created = data.items.len();
+    Ok(report)
+}
@@ -3,15 +3,33 @@
 
 mod models;
 mod database;
+mod migrations;
 mod fsrs;
+mod fsrs_optimizer;
+mod archive;
+mod anki;
 mod sir_scheduler;
+mod units;
+mod grading;
+mod formula;
+mod concept_graph;
+mod error_analysis;
+mod distractor_analysis;
+mod calibration;
+mod planner;
+mod exam;
+mod session_report;
+mod diagnostic;
+mod focused;
+mod irt;
+mod item_import;
 mod commands;
 
 use commands::AppState;
 use database::Database;
 use fsrs::FSRSScheduler;
 use sir_scheduler::SirScheduler;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 
 fn main() {
     let app_data_dir = tauri::api::path::app_data_dir(&tauri::Config::default())
@@ -21,12 +39,15 @@ fn main() {
     
     let db_path = app_data_dir.join("gapfinder.db");
     let db = Database::new(db_path).expect("Failed to initialize database");
-    let fsrs = FSRSScheduler::default();
+    let fsrs = match db.get_fsrs_parameters().expect("Failed to load FSRS parameters") {
+        Some(params) => FSRSScheduler::new(params),
+        None => FSRSScheduler::default(),
+    };
     let sir = SirScheduler::new();
     
     let state = AppState {
         db: Arc::new(db),
-        fsrs: Arc::new(fsrs),
+        fsrs: Arc::new(RwLock::new(fsrs)),
         sir: Arc::new(sir),
     };
 
@@ -37,25 +58,57 @@ fn main() {
             commands::get_all_concepts,
             commands::update_concept,
             commands::delete_concept,
+            commands::get_items_by_concept,
+            commands::add_concept_edge,
+            commands::remove_concept_edge,
+            commands::get_concept_edges,
             commands::create_item,
             commands::get_all_items,
             commands::update_item,
             commands::delete_item,
+            commands::get_concepts_by_item,
             commands::submit_attempt,
             commands::get_attempts_by_item,
+            commands::optimize_fsrs_parameters,
+            commands::calibrate_items,
             commands::create_session,
             commands::complete_session,
+            commands::get_session_items,
+            commands::get_next_diagnostic_item,
+            commands::get_next_focused_item,
+            commands::get_concept_abilities,
+            commands::get_exam_results,
+            commands::get_session_report,
             commands::get_all_sessions,
             commands::get_concept_mastery,
+            commands::get_root_cause_analysis,
+            commands::get_error_analysis,
+            commands::get_distractor_analysis,
+            commands::get_calibration,
+            commands::get_confidence_mapping,
+            commands::set_confidence_mapping,
             commands::get_daily_plan,
             commands::get_performance_trends,
             commands::import_concepts_from_csv,
+            commands::import_items_from_csv,
+            commands::import_anki_deck,
+            commands::export_anki_deck,
             commands::get_next_review_item,
             commands::get_item_count,
             commands::get_due_count,
+            commands::backup_database,
+            commands::export_collection,
+            commands::restore_collection,
             commands::clear_all_data,
             commands::create_learning_material,
             commands::get_learning_material,
+            commands::get_all_learning_materials,
+            commands::search_learning_materials,
+            commands::update_learning_material,
+            commands::delete_learning_material,
+            commands::get_concepts_by_material,
+            commands::get_items_by_material,
+            commands::get_material_progress,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
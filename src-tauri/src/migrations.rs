@@ -0,0 +1,343 @@
+use anyhow::{bail, Context, Result};
+use rusqlite::Connection;
+
+/// One schema step. `apply` runs inside a transaction together with the
+/// `user_version` bump, so a failed step leaves the database untouched.
+struct Migration {
+    description: &'static str,
+    apply: fn(&Connection) -> Result<()>,
+}
+
+/// Ordered migration registry. Step N (1-based) upgrades a database at
+/// `user_version` N-1 to N. Never edit or reorder a shipped step; append a new one.
+const MIGRATIONS: &[Migration] = &[
+    Migration { description: "base schema", apply: base_schema },
+    Migration { description: "graded attempts", apply: graded_attempts },
+    Migration { description: "per-item review state", apply: item_states },
+    Migration { description: "settings", apply: settings },
+    Migration { description: "item-concept links", apply: item_concepts },
+    Migration { description: "concept graph", apply: concept_edges },
+    Migration { description: "session item sets", apply: session_items },
+    Migration { description: "concept ability estimates", apply: concept_abilities },
+    Migration { description: "item discrimination", apply: item_discrimination },
+    Migration { description: "learning material lookups", apply: material_indexes },
+    Migration { description: "item state cascade", apply: item_state_cascade },
+];
+
+/// Schema version this build expects.
+pub const SCHEMA_VERSION: i32 = MIGRATIONS.len() as i32;
+
+pub fn current_version(conn: &Connection) -> Result<i32> {
+    Ok(conn.pragma_query_value(None, "user_version", |row| row.get(0))?)
+}
+
+/// True when the file already holds tables, i.e. upgrading touches real data.
+pub fn has_user_tables(conn: &Connection) -> Result<bool> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
+
+/// Applies every pending migration in order, one transaction per step.
+pub fn migrate(conn: &mut Connection) -> Result<()> {
+    let current = current_version(conn)?;
+    if current > SCHEMA_VERSION {
+        bail!(
+            "Database schema version {} is newer than this app supports ({}); please update GapFinder",
+            current,
+            SCHEMA_VERSION
+        );
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(current as usize) {
+        let version = index as i32 + 1;
+        let tx = conn.transaction()?;
+        (migration.apply)(&tx)
+            .with_context(|| format!("Migration {} ({}) failed", version, migration.description))?;
+        tx.pragma_update(None, "user_version", version)?;
+        tx.commit()
+            .with_context(|| format!("Migration {} ({}) failed to commit", version, migration.description))?;
+    }
+    Ok(())
+}
+
+fn has_column(conn: &Connection, table: &str, column: &str) -> Result<bool> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let names = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(names.iter().any(|name| name == column))
+}
+
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, definition: &str) -> Result<()> {
+    if !has_column(conn, table, column)? {
+        conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])?;
+    }
+    Ok(())
+}
+
+/// Creates the original tables. Databases made before versioning existed may
+/// have any subset of the later columns, so those are added only when missing.
+fn base_schema(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS learning_materials (
+            id TEXT PRIMARY KEY,
+            content TEXT NOT NULL,
+            domain TEXT NOT NULL,
+            encoding_date TEXT NOT NULL,
+            created_at TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS concepts (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            domain TEXT NOT NULL,
+            subdomain TEXT,
+            description TEXT,
+            tags TEXT,
+            learning_material_id TEXT,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS items (
+            id TEXT PRIMARY KEY,
+            stem TEXT NOT NULL,
+            item_type TEXT NOT NULL,
+            concept_ids TEXT NOT NULL,
+            difficulty INTEGER NOT NULL,
+            source TEXT,
+            explanation TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS attempts (
+            id TEXT PRIMARY KEY,
+            item_id TEXT NOT NULL,
+            session_id TEXT,
+            user_answer TEXT NOT NULL,
+            is_correct BOOLEAN NOT NULL,
+            confidence INTEGER NOT NULL,
+            time_spent_ms INTEGER NOT NULL,
+            attempted_at TEXT NOT NULL,
+            sir_phase TEXT NOT NULL,
+            next_review_date TEXT NOT NULL,
+            metacognitive TEXT,
+            stability REAL NOT NULL,
+            difficulty REAL NOT NULL,
+            elapsed_days INTEGER NOT NULL,
+            scheduled_days INTEGER NOT NULL,
+            review_state TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS sessions (
+            id TEXT PRIMARY KEY,
+            session_type TEXT NOT NULL,
+            started_at TEXT NOT NULL,
+            completed_at TEXT,
+            total_items INTEGER NOT NULL,
+            completed_items INTEGER NOT NULL,
+            accuracy REAL NOT NULL,
+            average_confidence REAL NOT NULL
+        );",
+    )?;
+
+    add_column_if_missing(conn, "concepts", "learning_material_id", "TEXT")?;
+    add_column_if_missing(conn, "attempts", "sir_phase", "TEXT NOT NULL DEFAULT 'Encoding'")?;
+    add_column_if_missing(
+        conn,
+        "attempts",
+        "next_review_date",
+        "TEXT NOT NULL DEFAULT '1970-01-01T00:00:00Z'",
+    )?;
+    add_column_if_missing(conn, "attempts", "metacognitive", "TEXT")?;
+    add_column_if_missing(conn, "sessions", "accuracy", "REAL NOT NULL DEFAULT 0.0")?;
+
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_attempts_item_id ON attempts(item_id);
+        CREATE INDEX IF NOT EXISTS idx_attempts_session_id ON attempts(session_id);
+        CREATE INDEX IF NOT EXISTS idx_attempts_next_review ON attempts(next_review_date);",
+    )?;
+    Ok(())
+}
+
+fn graded_attempts(conn: &Connection) -> Result<()> {
+    add_column_if_missing(conn, "attempts", "score", "REAL")?;
+    add_column_if_missing(conn, "attempts", "feedback", "TEXT")?;
+    Ok(())
+}
+
+/// Latest memory state per item, maintained by `Database::create_attempt`,
+/// backfilled from each item's most recent attempt.
+fn item_states(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS item_states (
+            item_id TEXT PRIMARY KEY,
+            last_attempt_id TEXT NOT NULL,
+            last_attempted_at TEXT NOT NULL,
+            stability REAL NOT NULL,
+            difficulty REAL NOT NULL,
+            review_state TEXT NOT NULL,
+            sir_phase TEXT NOT NULL,
+            scheduled_days INTEGER NOT NULL,
+            next_review_date TEXT NOT NULL,
+            reps INTEGER NOT NULL,
+            lapses INTEGER NOT NULL
+        );
+
+        INSERT OR IGNORE INTO item_states
+        SELECT a.item_id, a.id, a.attempted_at, a.stability, a.difficulty, a.review_state, a.sir_phase,
+               a.scheduled_days, a.next_review_date,
+               (SELECT COUNT(*) FROM attempts c WHERE c.item_id = a.item_id),
+               (SELECT COUNT(*) FROM attempts c WHERE c.item_id = a.item_id
+                   AND c.is_correct = 0 AND c.review_state = '\"Relearning\"')
+        FROM attempts a
+        WHERE a.attempted_at = (SELECT MAX(b.attempted_at) FROM attempts b WHERE b.item_id = a.item_id);",
+    )?;
+    Ok(())
+}
+
+/// Key/value app settings stored as JSON
+fn settings(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Moves `items.concept_ids` (a JSON array) into the `item_concepts` join table.
+/// Links are removed with either side: deleting an item drops its links, and
+/// deleting a concept detaches it from its items. Ids that point at concepts
+/// which no longer exist are discarded.
+fn item_concepts(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TEMP TABLE legacy_item_concepts AS
+            SELECT items.id AS item_id, links.value AS concept_id, CAST(links.key AS INTEGER) AS position
+            FROM items, json_each(items.concept_ids) AS links
+            WHERE json_valid(items.concept_ids);
+
+        CREATE TABLE items_new (
+            id TEXT PRIMARY KEY,
+            stem TEXT NOT NULL,
+            item_type TEXT NOT NULL,
+            difficulty INTEGER NOT NULL,
+            source TEXT,
+            explanation TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );
+        INSERT INTO items_new
+            SELECT id, stem, item_type, difficulty, source, explanation, created_at, updated_at FROM items;
+        DROP TABLE items;
+        ALTER TABLE items_new RENAME TO items;
+
+        CREATE TABLE item_concepts (
+            item_id TEXT NOT NULL REFERENCES items(id) ON DELETE CASCADE,
+            concept_id TEXT NOT NULL REFERENCES concepts(id) ON DELETE CASCADE,
+            position INTEGER NOT NULL,
+            PRIMARY KEY (item_id, concept_id)
+        );
+        CREATE INDEX idx_item_concepts_concept_id ON item_concepts(concept_id);
+
+        INSERT OR IGNORE INTO item_concepts (item_id, concept_id, position)
+            SELECT item_id, concept_id, position FROM legacy_item_concepts
+            WHERE concept_id IN (SELECT id FROM concepts)
+            ORDER BY item_id, position;
+
+        DROP TABLE legacy_item_concepts;",
+    )?;
+    Ok(())
+}
+
+/// Directed relations between concepts. `from` is a prerequisite of, or part
+/// of, `to`; `related` has no direction but is still stored once.
+fn concept_edges(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE concept_edges (
+            from_concept_id TEXT NOT NULL REFERENCES concepts(id) ON DELETE CASCADE,
+            to_concept_id TEXT NOT NULL REFERENCES concepts(id) ON DELETE CASCADE,
+            relation TEXT NOT NULL CHECK (relation IN ('prerequisite', 'part-of', 'related')),
+            created_at TEXT NOT NULL,
+            PRIMARY KEY (from_concept_id, to_concept_id, relation),
+            CHECK (from_concept_id != to_concept_id)
+        );
+        CREATE INDEX idx_concept_edges_to ON concept_edges(to_concept_id);",
+    )?;
+    Ok(())
+}
+
+/// The fixed set of items drawn for an exam, in the order they were drawn.
+fn session_items(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE session_items (
+            session_id TEXT NOT NULL REFERENCES sessions(id) ON DELETE CASCADE,
+            item_id TEXT NOT NULL REFERENCES items(id) ON DELETE CASCADE,
+            position INTEGER NOT NULL,
+            PRIMARY KEY (session_id, item_id)
+        );",
+    )?;
+    Ok(())
+}
+
+/// Latest adaptive-diagnostic ability estimate per concept, in logits.
+fn concept_abilities(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE concept_abilities (
+            concept_id TEXT PRIMARY KEY REFERENCES concepts(id) ON DELETE CASCADE,
+            ability REAL NOT NULL,
+            standard_error REAL NOT NULL,
+            items_used INTEGER NOT NULL,
+            session_id TEXT REFERENCES sessions(id) ON DELETE SET NULL,
+            estimated_at TEXT NOT NULL
+        );",
+    )?;
+    Ok(())
+}
+
+/// IRT discrimination per item, written by the calibration job.
+fn item_discrimination(conn: &Connection) -> Result<()> {
+    add_column_if_missing(conn, "items", "discrimination", "REAL NOT NULL DEFAULT 1.0")?;
+    Ok(())
+}
+
+/// Material-to-concept navigation and listing materials by domain.
+fn material_indexes(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_concepts_learning_material_id ON concepts(learning_material_id);
+        CREATE INDEX IF NOT EXISTS idx_learning_materials_domain ON learning_materials(domain);",
+    )?;
+    Ok(())
+}
+
+/// Ties `item_states` to its item so deleting the item removes its state.
+/// Rows left behind by items deleted earlier are dropped.
+fn item_state_cascade(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE item_states_new (
+            item_id TEXT PRIMARY KEY REFERENCES items(id) ON DELETE CASCADE,
+            last_attempt_id TEXT NOT NULL,
+            last_attempted_at TEXT NOT NULL,
+            stability REAL NOT NULL,
+            difficulty REAL NOT NULL,
+            review_state TEXT NOT NULL,
+            sir_phase TEXT NOT NULL,
+            scheduled_days INTEGER NOT NULL,
+            next_review_date TEXT NOT NULL,
+            reps INTEGER NOT NULL,
+            lapses INTEGER NOT NULL
+        );
+        INSERT INTO item_states_new
+            SELECT * FROM item_states WHERE item_id IN (SELECT id FROM items);
+        DROP TABLE item_states;
+        ALTER TABLE item_states_new RENAME TO item_states;",
+    )?;
+    Ok(())
+}
@@ -1,5 +1,6 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use uuid::Uuid;
 
 // ==================== Learning Material ====================
@@ -26,6 +27,19 @@ impl LearningMaterial {
     }
 }
 
+/// How far the concepts built on one material have come.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaterialProgress {
+    pub material_id: String,
+    pub domain: String,
+    pub concept_count: i32,
+    pub attempted_concepts: i32,
+    /// Attempted concepts below the weak threshold
+    pub weak_concepts: i32,
+    pub mastery_score: f64, // mean over linked concepts, unattempted ones at 0
+    pub concepts: Vec<ConceptMastery>, // weakest first
+}
+
 // ==================== Concept ====================
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -58,6 +72,43 @@ impl Concept {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConceptRelation {
+    /// `from` must be understood before `to`
+    Prerequisite,
+    /// `from` is a component of `to`
+    PartOf,
+    Related,
+}
+
+impl ConceptRelation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConceptRelation::Prerequisite => "prerequisite",
+            ConceptRelation::PartOf => "part-of",
+            ConceptRelation::Related => "related",
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        match text {
+            "prerequisite" => Some(ConceptRelation::Prerequisite),
+            "part-of" => Some(ConceptRelation::PartOf),
+            "related" => Some(ConceptRelation::Related),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConceptEdge {
+    pub from_concept_id: String,
+    pub to_concept_id: String,
+    pub relation: ConceptRelation,
+    pub created_at: DateTime<Utc>,
+}
+
 // ==================== Item ====================
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -78,6 +129,9 @@ pub enum ItemType {
         correct_answer: f64,
         unit: String,
         worked_solution: Vec<String>,
+        // Relative tolerance (0.01 = 1%); the grader's default when absent
+        #[serde(default)]
+        tolerance: Option<f64>,
     },
     #[serde(rename = "case")]
     CaseVignette {
@@ -89,6 +143,19 @@ pub enum ItemType {
     },
 }
 
+impl ItemType {
+    /// The serialized type tag, e.g. "mcq" or "calc".
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ItemType::Mcq { .. } => "mcq",
+            ItemType::FreeRecall { .. } => "free-recall",
+            ItemType::Calculation { .. } => "calc",
+            ItemType::CaseVignette { .. } => "case",
+            ItemType::Cloze { .. } => "cloze",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct McqOption {
     pub id: String,
@@ -102,6 +169,13 @@ pub struct CalcVariable {
     pub name: String,
     pub value: f64,
     pub unit: String,
+    // Range for randomized variants; the item is fixed when either bound is absent
+    #[serde(default)]
+    pub min: Option<f64>,
+    #[serde(default)]
+    pub max: Option<f64>,
+    #[serde(default)]
+    pub step: Option<f64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -126,6 +200,9 @@ pub struct Item {
     pub item_type: ItemType,
     pub concept_ids: Vec<String>,
     pub difficulty: i32,
+    /// IRT discrimination; 1.0 until calibrated from attempts
+    #[serde(default = "default_discrimination")]
+    pub discrimination: f64,
     pub source: Option<String>,
     pub explanation: String,
     pub created_at: DateTime<Utc>,
@@ -141,6 +218,7 @@ impl Item {
             item_type,
             concept_ids,
             difficulty: 50,
+            discrimination: default_discrimination(),
             source: None,
             explanation,
             created_at: now,
@@ -149,6 +227,73 @@ impl Item {
     }
 }
 
+fn default_discrimination() -> f64 {
+    1.0
+}
+
+// ==================== Grading ====================
+
+/// A learner's structured answer, tagged the same way as `ItemType`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ItemAnswer {
+    #[serde(rename = "mcq")]
+    Mcq {
+        option_id: String,
+    },
+    #[serde(rename = "free-recall")]
+    FreeRecall {
+        text: String,
+    },
+    #[serde(rename = "calc")]
+    Calculation {
+        value: f64,
+        unit: String,
+    },
+    #[serde(rename = "case")]
+    CaseVignette {
+        steps: Vec<CaseStepAnswer>,
+    },
+    #[serde(rename = "cloze")]
+    Cloze {
+        // blank id -> answer
+        blanks: HashMap<String, String>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaseStepAnswer {
+    pub step_number: i32,
+    pub answer: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum AnswerError {
+    WrongUnit,        // right number, wrong or unconvertible unit
+    IncompatibleUnit, // unit measures a different quantity
+    UnknownUnit,
+    OutOfTolerance,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartFeedback {
+    pub part_id: String,
+    pub is_correct: bool,
+    pub points_awarded: f64,
+    pub points_possible: f64,
+    pub expected: String,
+    pub message: Option<String>,
+    #[serde(default)]
+    pub error: Option<AnswerError>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GradeResult {
+    pub is_correct: bool,
+    pub score: f64, // 0.0 - 1.0
+    pub feedback: Vec<PartFeedback>,
+}
+
 // ==================== Attempt ====================
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -181,6 +326,9 @@ pub struct Attempt {
     pub sir_phase: SirPhase,
     pub next_review_date: DateTime<Utc>,
     pub metacognitive: Option<MetacognitiveReflection>,
+    pub score: f64,
+    #[serde(default)]
+    pub feedback: Vec<PartFeedback>,
     // Keep legacy FSRS fields for backward compatibility
     pub stability: f64,
     pub difficulty: f64,
@@ -219,6 +367,8 @@ impl Attempt {
             sir_phase: SirPhase::Encoding,
             next_review_date: now,
             metacognitive: None,
+            score: if is_correct { 1.0 } else { 0.0 },
+            feedback: Vec::new(),
             stability: 0.0,
             difficulty: 0.0,
             elapsed_days: 0,
@@ -228,6 +378,22 @@ impl Attempt {
     }
 }
 
+/// Memory state an item carries between reviews, updated with every attempt.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ItemState {
+    pub item_id: String,
+    pub last_attempt_id: String,
+    pub last_attempted_at: DateTime<Utc>,
+    pub stability: f64,
+    pub difficulty: f64,
+    pub review_state: ReviewState,
+    pub sir_phase: SirPhase,
+    pub scheduled_days: i32,
+    pub next_review_date: DateTime<Utc>,
+    pub reps: i32,
+    pub lapses: i32,
+}
+
 // ==================== Session ====================
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -246,10 +412,32 @@ pub struct Session {
 pub enum SessionType {
     Mixed,
     Diagnostic,
-    Focused { concept_id: String },
+    Focused {
+        concept_id: String,
+        #[serde(default)]
+        scope: FocusScope,
+        /// Accuracy (0-1) over the latest answers that ends the session
+        #[serde(default)]
+        target_accuracy: Option<f64>,
+        /// Concept mastery (0-100) that ends the session
+        #[serde(default)]
+        target_mastery: Option<f64>,
+    },
     Exam { time_limit_ms: i64 },
 }
 
+/// Items a focused session draws on besides the concept's own.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FocusScope {
+    #[default]
+    Concept,
+    /// Also items of concepts sharing a tag with it
+    Tags,
+    /// Also items of concepts in its domain
+    Domain,
+}
+
 impl Session {
     pub fn new(session_type: SessionType, total_items: i32) -> Self {
         Self {
@@ -263,6 +451,96 @@ impl Session {
             average_confidence: 0.0,
         }
     }
+
+    /// When an exam runs out of time; `None` for other session types.
+    pub fn deadline(&self) -> Option<DateTime<Utc>> {
+        match self.session_type {
+            SessionType::Exam { time_limit_ms } => Some(self.started_at + Duration::milliseconds(time_limit_ms)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionConceptStats {
+    pub concept_id: String,
+    pub concept_name: String,
+    pub attempts: i32,
+    pub correct: i32,
+    pub accuracy: f64,
+    pub avg_time_ms: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionMiss {
+    pub attempt_id: String,
+    pub item_id: String,
+    pub stem: String,
+    pub user_answer: String,
+    /// Expected answers for the parts that were wrong
+    pub expected: Vec<String>,
+    pub confidence: i32,
+    pub time_spent_ms: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionReport {
+    pub session: Session,
+    pub attempts: i32,
+    pub correct: i32,
+    pub accuracy: f64,
+    pub total_time_ms: i64,
+    pub avg_time_ms: f64,
+    pub calibration: CalibrationReport,
+    /// Weakest concept first
+    pub by_concept: Vec<SessionConceptStats>,
+    pub misses: Vec<SessionMiss>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionItem {
+    pub session_id: String,
+    pub item_id: String,
+    pub position: i32,
+}
+
+/// Ability on a concept estimated by an adaptive diagnostic, in logits on the
+/// same scale as item difficulty (0 = an item of difficulty 50).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConceptAbility {
+    pub concept_id: String,
+    pub ability: f64,
+    pub standard_error: f64,
+    pub items_used: i32,
+    pub session_id: Option<String>,
+    pub estimated_at: DateTime<Utc>,
+}
+
+/// Accuracy on one concept or domain within an exam. Unanswered items count
+/// as wrong.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExamBreakdown {
+    pub key: String,
+    pub label: String,
+    pub items: i32,
+    pub answered: i32,
+    pub correct: i32,
+    pub accuracy: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExamResults {
+    pub session: Session,
+    pub deadline: DateTime<Utc>,
+    pub timed_out: bool,
+    pub total_items: i32,
+    pub answered: i32,
+    pub correct: i32,
+    pub score: f64, // mean attempt score (0-1) over every exam item
+    pub by_concept: Vec<ExamBreakdown>,
+    pub by_domain: Vec<ExamBreakdown>,
+    /// Graded attempts with full feedback, in the order they were submitted
+    pub attempts: Vec<Attempt>,
 }
 
 // ==================== Analytics ====================
@@ -278,7 +556,38 @@ pub struct ConceptMastery {
     pub brier_score: f64,
     pub last_attempted: Option<DateTime<Utc>>,
     pub stability: f64,
+    /// "improving", "declining" or "stable"
     pub trend: String,
+    /// Fitted change in performance (score blended with FSRS retrievability),
+    /// in points (0-100) per day
+    #[serde(default)]
+    pub trend_slope: f64,
+    #[serde(default)]
+    pub trend_window_days: i64,
+}
+
+/// A weak concept found by walking back from the analysed concept.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RootCause {
+    pub concept_id: String,
+    pub concept_name: String,
+    pub mastery_score: f64,
+    pub attempts: i32,
+    /// Edges between the analysed concept and this one
+    pub depth: usize,
+    /// Concept ids from the analysed concept down to this one, inclusive
+    pub path: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RootCauseReport {
+    pub concept_id: String,
+    pub concept_name: String,
+    pub mastery_score: f64,
+    pub threshold: f64,
+    pub is_weak: bool,
+    /// Weakest-first; empty when every prerequisite is above the threshold
+    pub root_causes: Vec<RootCause>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -289,6 +598,11 @@ pub struct DailyPlan {
     pub total_items: i32,
     pub estimated_time_min: i32,
     pub coverage_percent: f64,
+    pub budget_min: i32,
+    /// Reviews and diagnostics in the order to study them
+    pub items: Vec<PlannedItem>,
+    /// Due or new items that did not fit in the budget, highest priority first
+    pub overflow: Vec<PlannedItem>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -297,16 +611,113 @@ pub struct PlannedItem {
     pub concept_id: String,
     pub reason: String,
     pub priority: i32,
+    pub domain: String,
+    pub estimated_time_ms: i64,
+    pub retrievability: Option<f64>, // reviews only
+    pub due_date: Option<DateTime<Utc>>,
+}
+
+/// Narrows analytics to attempts on items linked to a concept or domain, and
+/// to a date range. Unset fields do not filter.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AttemptFilter {
+    #[serde(default)]
+    pub concept_id: Option<String>,
+    #[serde(default)]
+    pub domain: Option<String>,
+    #[serde(default)]
+    pub from: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub to: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ErrorAnalysis {
     pub error_type: String,
     pub count: i32,
-    pub percentage: f64,
+    pub percentage: f64, // of all misses; one miss can fall under several types
     pub example_items: Vec<String>,
 }
 
+/// How often one MCQ option was picked.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OptionStats {
+    pub option_id: String,
+    pub text: String,
+    pub is_correct: bool,
+    pub selections: i32,
+    pub selection_rate: f64, // of all attempts on the item
+    pub high_mastery_selections: i32,
+    pub high_mastery_rate: f64, // of high-mastery attempts on the item
+    pub flag: Option<String>, // "non_functioning" | "chosen_over_key"
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DistractorReport {
+    pub item_id: String,
+    pub stem: String,
+    pub attempts: i32,
+    pub high_mastery_attempts: i32,
+    /// Answers that match no current option
+    pub unmatched: i32,
+    pub options: Vec<OptionStats>,
+    pub flags: Vec<String>,
+}
+
+/// Probability of being correct that each confidence rating (1-5) stands for.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfidenceMapping {
+    pub probabilities: [f64; 5],
+}
+
+impl Default for ConfidenceMapping {
+    fn default() -> Self {
+        Self { probabilities: [0.1, 0.3, 0.5, 0.7, 0.9] }
+    }
+}
+
+impl ConfidenceMapping {
+    pub fn probability(&self, confidence: i32) -> f64 {
+        self.probabilities[(confidence.clamp(1, 5) - 1) as usize]
+    }
+}
+
+/// One point of a reliability diagram: attempts at a single confidence rating.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CalibrationBin {
+    pub confidence: i32,
+    pub forecast: f64,
+    pub observed: f64, // share of these attempts that were correct
+    pub count: i32,
+}
+
+/// Calibration of one group of attempts. `brier_score` equals
+/// `reliability - resolution + uncertainty` (Murphy decomposition).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CalibrationReport {
+    pub key: String,
+    pub label: String,
+    pub attempts: i32,
+    pub brier_score: f64,
+    pub reliability: f64,
+    pub resolution: f64,
+    pub uncertainty: f64,
+    /// Count-weighted gap where forecasts exceed accuracy, 0-1
+    pub overconfidence: f64,
+    /// Count-weighted gap where accuracy exceeds forecasts, 0-1
+    pub underconfidence: f64,
+    pub bins: Vec<CalibrationBin>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CalibrationAnalysis {
+    pub mapping: ConfidenceMapping,
+    pub overall: CalibrationReport,
+    pub by_concept: Vec<CalibrationReport>,
+    pub by_domain: Vec<CalibrationReport>,
+    pub by_item_type: Vec<CalibrationReport>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PerformanceTrend {
     pub date: DateTime<Utc>,
@@ -314,3 +725,154 @@ pub struct PerformanceTrend {
     pub items_completed: i32,
     pub avg_confidence: f64,
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FsrsOptimizationReport {
+    pub reviews_used: i32,
+    pub log_loss_before: f64,
+    pub log_loss_after: f64,
+    pub rmse_before: f64,
+    pub rmse_after: f64,
+    pub weights: Vec<f64>,
+    pub applied: bool,
+}
+
+/// Item response model fitted by `calibrate_items`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum IrtModel {
+    /// Rasch: difficulty only, every item discriminates equally
+    #[serde(rename = "1pl")]
+    OnePl,
+    /// Difficulty and discrimination
+    #[serde(rename = "2pl")]
+    TwoPl,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ItemCalibration {
+    pub item_id: String,
+    pub stem: String,
+    pub responses: i32,
+    pub proportion_correct: f64,
+    pub difficulty_before: i32,
+    pub difficulty: i32,
+    pub discrimination_before: f64,
+    pub discrimination: f64,
+    pub flag: Option<String>, // "negative_discrimination" | "low_discrimination"
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IrtCalibrationReport {
+    pub model: IrtModel,
+    pub responses_used: i32,
+    /// Sessions (or days, for attempts outside a session) treated as test takers
+    pub occasions: i32,
+    pub items: Vec<ItemCalibration>, // flagged first
+    pub flagged: i32,
+    /// Items left unchanged for having too few responses
+    pub skipped: i32,
+}
+
+// ==================== Collection Archive ====================
+
+/// Every user-authored table, as written to and read from a collection archive.
+/// `item_states` is derived from attempts and rebuilt on restore.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CollectionData {
+    pub learning_materials: Vec<LearningMaterial>,
+    pub concepts: Vec<Concept>,
+    pub items: Vec<Item>,
+    pub attempts: Vec<Attempt>,
+    pub sessions: Vec<Session>,
+    #[serde(default)]
+    pub concept_edges: Vec<ConceptEdge>,
+    #[serde(default)]
+    pub session_items: Vec<SessionItem>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RestoreMode {
+    /// Wipe the collection, then load the archive.
+    Replace,
+    /// Insert new rows and overwrite existing rows with the same id; keep everything else.
+    Merge,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveSummary {
+    pub schema_version: i32,
+    pub created_at: DateTime<Utc>,
+    pub checksum: String,
+    pub learning_materials: usize,
+    pub concepts: usize,
+    pub items: usize,
+    pub attempts: usize,
+    pub sessions: usize,
+}
+
+// ==================== Anki ====================
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AnkiImportReport {
+    pub items_created: usize,
+    pub concepts_created: usize,
+    pub attempts_created: usize,
+    /// Notes already imported from the same deck, matched by Anki GUID
+    pub notes_skipped: usize,
+    /// Notes whose type or content has no GapFinder equivalent
+    pub notes_unsupported: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AnkiExportReport {
+    pub notes_exported: usize,
+    pub cards_exported: usize,
+    pub reviews_exported: usize,
+    /// Calculation and case items, which have no Anki note type
+    pub items_skipped: usize,
+}
+
+// ==================== CSV Import ====================
+
+/// Header names of the columns holding each item field. Only `stem` and
+/// `correct_answer` are required.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ItemColumnMapping {
+    pub stem: String,
+    pub correct_answer: String,
+    /// `mcq`, `free-recall` or `cloze`; rows default to free recall
+    #[serde(default)]
+    pub item_type: Option<String>,
+    /// MCQ options separated by `|`
+    #[serde(default)]
+    pub options: Option<String>,
+    #[serde(default)]
+    pub explanation: Option<String>,
+    #[serde(default)]
+    pub difficulty: Option<String>,
+    #[serde(default)]
+    pub source: Option<String>,
+    /// Concept names separated by `;`
+    #[serde(default)]
+    pub concepts: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportRowError {
+    /// 1-based line in the file, counting the header
+    pub row: usize,
+    pub column: Option<String>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ItemImportReport {
+    pub dry_run: bool,
+    pub rows: usize,
+    pub valid_rows: usize,
+    pub items_created: usize,
+    /// Names of concepts that did not exist and are (or would be) created
+    pub new_concepts: Vec<String>,
+    pub errors: Vec<ImportRowError>,
+}
@@ -0,0 +1,185 @@
+use crate::fsrs::FSRSScheduler;
+use crate::models::{Concept, DailyPlan, Item, ItemState, PlannedItem};
+use crate::sir_scheduler::SirScheduler;
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
+
+/// Minutes planned when the caller gives no budget.
+pub const DEFAULT_BUDGET_MIN: i32 = 30;
+/// Time assumed for an item when nothing similar has been attempted yet.
+const DEFAULT_ITEM_MS: f64 = 120_000.0;
+/// Historical averages are clamped to this range so an answer left open
+/// overnight does not push everything else out of the plan.
+const MIN_ITEM_MS: f64 = 10_000.0;
+const MAX_ITEM_MS: f64 = 600_000.0;
+/// Due reviews below this retrievability are marked high priority.
+const AT_RISK_RETRIEVABILITY: f64 = 0.7;
+
+/// What the planner knows about the collection.
+pub struct PlanInput<'a> {
+    pub items: &'a [Item],
+    pub states: &'a HashMap<String, ItemState>,
+    pub concepts: &'a HashMap<String, Concept>,
+    /// Average `time_spent_ms` per attempted item
+    pub average_time_ms: &'a HashMap<String, f64>,
+}
+
+/// Fills `budget_min` minutes with due reviews, lowest retrievability first,
+/// then with new items, preferring concepts that have never been practised.
+/// Items are taken first-fit, so a long item that does not fit leaves room for
+/// shorter ones behind it; everything left over is returned as overflow. The
+/// chosen items are then ordered so consecutive items switch domain, or at
+/// least concept, whenever possible.
+pub fn plan(
+    input: &PlanInput,
+    fsrs: &FSRSScheduler,
+    sir: &SirScheduler,
+    budget_min: i32,
+    now: DateTime<Utc>,
+) -> DailyPlan {
+    let estimate = TimeEstimator::new(input);
+    let attempted_concepts: HashSet<&str> = input
+        .items
+        .iter()
+        .filter(|item| input.states.contains_key(&item.id))
+        .flat_map(|item| item.concept_ids.iter().map(String::as_str))
+        .collect();
+
+    let mut reviews: Vec<PlannedItem> = Vec::new();
+    let mut new_items: Vec<(bool, PlannedItem)> = Vec::new();
+    for item in input.items {
+        let mut planned = planned_item(item, input.concepts, estimate.for_item(item));
+        match input.states.get(&item.id) {
+            Some(state) if sir.is_due(state.next_review_date, now) => {
+                let elapsed_days = (now - state.last_attempted_at).num_days().max(0) as i32;
+                let retrievability = fsrs.retrievability(elapsed_days, state.stability);
+                planned.reason = "due_for_review".to_string();
+                planned.priority = if retrievability < AT_RISK_RETRIEVABILITY { 3 } else { 1 };
+                planned.retrievability = Some(retrievability);
+                planned.due_date = Some(state.next_review_date);
+                reviews.push(planned);
+            }
+            Some(_) => {}
+            None => {
+                let opens_concept = item.concept_ids.iter().any(|id| !attempted_concepts.contains(id.as_str()));
+                planned.reason = "new_concept".to_string();
+                planned.priority = 2;
+                new_items.push((opens_concept, planned));
+            }
+        }
+    }
+
+    reviews.sort_by(|a, b| {
+        let ra = a.retrievability.unwrap_or(0.0);
+        let rb = b.retrievability.unwrap_or(0.0);
+        ra.total_cmp(&rb).then(a.due_date.cmp(&b.due_date))
+    });
+    // Stable sort keeps the collection order among equally new items
+    new_items.sort_by_key(|(opens_concept, _)| !opens_concept);
+
+    let budget_ms = budget_min.max(0) as i64 * 60_000;
+    let mut used_ms = 0;
+    let mut chosen: Vec<PlannedItem> = Vec::new();
+    let mut overflow: Vec<PlannedItem> = Vec::new();
+    for planned in reviews.into_iter().chain(new_items.into_iter().map(|(_, p)| p)) {
+        if used_ms + planned.estimated_time_ms <= budget_ms {
+            used_ms += planned.estimated_time_ms;
+            chosen.push(planned);
+        } else {
+            overflow.push(planned);
+        }
+    }
+
+    let items = interleave(chosen);
+    let (reviews, diagnostics): (Vec<PlannedItem>, Vec<PlannedItem>) =
+        items.iter().cloned().partition(|p| p.reason == "due_for_review");
+
+    let coverage_percent = if !input.concepts.is_empty() {
+        (attempted_concepts.len() as f64 / input.concepts.len() as f64) * 100.0
+    } else {
+        0.0
+    };
+
+    DailyPlan {
+        date: now,
+        reviews,
+        diagnostics,
+        total_items: items.len() as i32,
+        estimated_time_min: ((used_ms as f64) / 60_000.0).ceil() as i32,
+        coverage_percent,
+        budget_min,
+        items,
+        overflow,
+    }
+}
+
+fn planned_item(item: &Item, concepts: &HashMap<String, Concept>, estimated_time_ms: i64) -> PlannedItem {
+    let concept_id = item.concept_ids.first().cloned().unwrap_or_default();
+    let domain = concepts.get(&concept_id).map(|c| c.domain.clone()).unwrap_or_default();
+    PlannedItem {
+        item_id: item.id.clone(),
+        concept_id,
+        reason: String::new(),
+        priority: 0,
+        domain,
+        estimated_time_ms,
+        retrievability: None,
+        due_date: None,
+    }
+}
+
+/// Per-item time from the item's own history, falling back to the average of
+/// attempted items of the same type, then of all attempted items.
+struct TimeEstimator<'a> {
+    average_time_ms: &'a HashMap<String, f64>,
+    by_kind: HashMap<&'static str, f64>,
+    overall: f64,
+}
+
+impl<'a> TimeEstimator<'a> {
+    fn new(input: &PlanInput<'a>) -> Self {
+        let mut sums: HashMap<&'static str, (f64, usize)> = HashMap::new();
+        for item in input.items {
+            if let Some(ms) = input.average_time_ms.get(&item.id) {
+                let (sum, count) = sums.entry(item.item_type.kind()).or_default();
+                *sum += ms;
+                *count += 1;
+            }
+        }
+        let (total, count) = sums.values().fold((0.0, 0), |(s, c), (sum, count)| (s + sum, c + count));
+        Self {
+            average_time_ms: input.average_time_ms,
+            by_kind: sums.into_iter().map(|(kind, (sum, count))| (kind, sum / count as f64)).collect(),
+            overall: if count > 0 { total / count as f64 } else { DEFAULT_ITEM_MS },
+        }
+    }
+
+    fn for_item(&self, item: &Item) -> i64 {
+        let ms = self
+            .average_time_ms
+            .get(&item.id)
+            .or_else(|| self.by_kind.get(item.item_type.kind()))
+            .copied()
+            .unwrap_or(self.overall);
+        ms.clamp(MIN_ITEM_MS, MAX_ITEM_MS).round() as i64
+    }
+}
+
+/// Reorders `items` (already in priority order) so each item differs from the
+/// one before in domain if possible, otherwise in concept, otherwise takes the
+/// next item in priority order.
+fn interleave(mut items: Vec<PlannedItem>) -> Vec<PlannedItem> {
+    let mut ordered: Vec<PlannedItem> = Vec::with_capacity(items.len());
+    while !items.is_empty() {
+        let next = match ordered.last() {
+            None => 0,
+            Some(previous) => items
+                .iter()
+                .position(|p| p.domain != previous.domain)
+                .or_else(|| items.iter().position(|p| p.concept_id != previous.concept_id))
+                .unwrap_or(0),
+        };
+        ordered.push(items.remove(next));
+    }
+    ordered
+}
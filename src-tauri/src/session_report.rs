@@ -0,0 +1,100 @@
+use crate::calibration;
+use crate::database::Database;
+use crate::exam;
+use crate::models::*;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap};
+
+/// Completes a session with stats aggregated from its stored attempts.
+/// Exams are scored over their whole item set instead; see `exam::complete`.
+pub fn complete(db: &Database, session: &Session, at: DateTime<Utc>) -> Result<Session> {
+    if session.completed_at.is_some() {
+        bail!("Session has already been completed");
+    }
+    if session.deadline().is_some() {
+        return exam::complete(db, session, at);
+    }
+
+    let attempts = db.get_attempts_by_session(&session.id)?;
+    let mut session = session.clone();
+    session.completed_at = Some(at);
+    session.completed_items = attempts.len() as i32;
+    session.accuracy = accuracy(&attempts);
+    session.average_confidence = if attempts.is_empty() {
+        0.0
+    } else {
+        attempts.iter().map(|a| a.confidence as f64).sum::<f64>() / attempts.len() as f64
+    };
+    db.update_session(&session)?;
+    Ok(session)
+}
+
+/// What happened in a session, built from its attempts. Refused for an exam
+/// that is still running, since misses include the expected answers.
+pub fn report(db: &Database, session_id: &str, mapping: &ConfidenceMapping) -> Result<SessionReport> {
+    let Some(session) = db.get_session(session_id)? else { bail!("Session not found") };
+    if session.deadline().is_some() && session.completed_at.is_none() {
+        bail!("Exam is still in progress");
+    }
+
+    let attempts = db.get_attempts_by_session(session_id)?;
+    let items: HashMap<String, Item> = db.get_all_items()?.into_iter().map(|i| (i.id.clone(), i)).collect();
+    let concepts: HashMap<String, Concept> = db.get_all_concepts()?.into_iter().map(|c| (c.id.clone(), c)).collect();
+
+    let mut by_concept: BTreeMap<&str, Vec<&Attempt>> = BTreeMap::new();
+    for attempt in &attempts {
+        let concept_ids = items.get(&attempt.item_id).map(|i| i.concept_ids.as_slice()).unwrap_or_default();
+        for concept_id in concept_ids {
+            by_concept.entry(concept_id.as_str()).or_default().push(attempt);
+        }
+    }
+    let mut by_concept: Vec<SessionConceptStats> = by_concept
+        .into_iter()
+        .map(|(concept_id, group)| SessionConceptStats {
+            concept_id: concept_id.to_string(),
+            concept_name: concepts.get(concept_id).map(|c| c.name.clone()).unwrap_or_default(),
+            attempts: group.len() as i32,
+            correct: group.iter().filter(|a| a.is_correct).count() as i32,
+            accuracy: group.iter().filter(|a| a.is_correct).count() as f64 / group.len() as f64,
+            avg_time_ms: group.iter().map(|a| a.time_spent_ms as f64).sum::<f64>() / group.len() as f64,
+        })
+        .collect();
+    by_concept.sort_by(|a, b| a.accuracy.total_cmp(&b.accuracy).then_with(|| a.concept_name.cmp(&b.concept_name)));
+
+    let misses = attempts
+        .iter()
+        .filter(|a| !a.is_correct)
+        .map(|a| SessionMiss {
+            attempt_id: a.id.clone(),
+            item_id: a.item_id.clone(),
+            stem: items.get(&a.item_id).map(|i| i.stem.clone()).unwrap_or_default(),
+            user_answer: a.user_answer.clone(),
+            expected: a.feedback.iter().filter(|f| !f.is_correct).map(|f| f.expected.clone()).collect(),
+            confidence: a.confidence,
+            time_spent_ms: a.time_spent_ms,
+        })
+        .collect();
+
+    let total_time_ms: i64 = attempts.iter().map(|a| a.time_spent_ms).sum();
+    let all: Vec<&Attempt> = attempts.iter().collect();
+    Ok(SessionReport {
+        attempts: attempts.len() as i32,
+        correct: attempts.iter().filter(|a| a.is_correct).count() as i32,
+        accuracy: accuracy(&attempts),
+        total_time_ms,
+        avg_time_ms: if attempts.is_empty() { 0.0 } else { total_time_ms as f64 / attempts.len() as f64 },
+        calibration: calibration::report(&session.id, "Session", &all, mapping),
+        by_concept,
+        misses,
+        session,
+    })
+}
+
+fn accuracy(attempts: &[Attempt]) -> f64 {
+    if attempts.is_empty() {
+        0.0
+    } else {
+        attempts.iter().filter(|a| a.is_correct).count() as f64 / attempts.len() as f64
+    }
+}
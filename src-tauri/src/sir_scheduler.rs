@@ -0,0 +1,450 @@
+use crate::fsrs::FSRSScheduler;
+use crate::models::{Attempt, ConfidenceMapping, MetacognitiveReflection, SirPhase};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{HashMap, HashSet};
+
+/// Minutes before a failed item comes back while it is being re-encoded.
+const RELEARN_MINUTES: i64 = 10;
+
+/// Days of history a mastery trend is fitted over.
+pub const TREND_WINDOW_DAYS: i64 = 30;
+/// Fewest attempts inside the window before a trend is reported.
+const MIN_TREND_ATTEMPTS: usize = 5;
+/// Change across the whole window, in score points, that counts as a trend.
+const TREND_THRESHOLD: f64 = 10.0;
+
+/// Schedules reviews along the SIR (Spaced, Interleaved, Retrieval) phase ladder:
+/// Encoding → ShortTermRetrieval (1-2d) → InterleavedRetrieval (3-5d)
+/// → MediumSpacing (7-10d) → IntegrationTransfer (14+d).
+#[derive(Default)]
+pub struct SirScheduler;
+
+impl SirScheduler {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Moves the attempt to its next phase and sets `next_review_date`.
+    ///
+    /// `attempt.sir_phase` must hold the phase the item was in before this review;
+    /// it is replaced by the phase the item moves to.
+    pub fn schedule(&self, attempt: &mut Attempt, metacognitive: Option<MetacognitiveReflection>) {
+        let needs_review = metacognitive.as_ref().map(|m| m.needs_review).unwrap_or(false);
+
+        let next_phase = if attempt.is_correct {
+            if needs_review {
+                attempt.sir_phase.clone()
+            } else {
+                Self::promote(&attempt.sir_phase)
+            }
+        } else if attempt.confidence >= 4 {
+            // A confident error points at a misconception, so start over
+            SirPhase::Encoding
+        } else {
+            Self::demote(&attempt.sir_phase)
+        };
+
+        let interval = if next_phase == SirPhase::Encoding {
+            Duration::minutes(RELEARN_MINUTES)
+        } else {
+            let days = self.interval_days(&next_phase, attempt.confidence, metacognitive.as_ref());
+            Duration::days(days)
+        };
+
+        attempt.sir_phase = next_phase;
+        attempt.next_review_date = attempt.attempted_at + interval;
+        attempt.metacognitive = metacognitive;
+    }
+
+    pub fn is_due(&self, next_review_date: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+        next_review_date <= now
+    }
+
+    /// Interval in days for a phase, picked inside the phase range by confidence
+    /// and shortened by the learner's reflection.
+    fn interval_days(
+        &self,
+        phase: &SirPhase,
+        confidence: i32,
+        metacognitive: Option<&MetacognitiveReflection>,
+    ) -> i64 {
+        let (min, max) = Self::phase_range(phase);
+        let fraction = (confidence.clamp(1, 5) - 1) as f64 / 4.0;
+        let mut days = min as f64 + fraction * (max - min) as f64;
+
+        if let Some(reflection) = metacognitive {
+            if reflection.felt_uncertain {
+                days *= 0.7;
+            }
+            if reflection.felt_confusing {
+                days *= 0.5;
+            }
+            if reflection.needs_review {
+                days = days.min(1.0);
+            }
+        }
+
+        (days.round() as i64).max(1)
+    }
+
+    fn phase_range(phase: &SirPhase) -> (i64, i64) {
+        match phase {
+            SirPhase::Encoding => (0, 0),
+            SirPhase::ShortTermRetrieval => (1, 2),
+            SirPhase::InterleavedRetrieval => (3, 5),
+            SirPhase::MediumSpacing => (7, 10),
+            SirPhase::IntegrationTransfer => (14, 28),
+        }
+    }
+
+    fn promote(phase: &SirPhase) -> SirPhase {
+        match phase {
+            SirPhase::Encoding => SirPhase::ShortTermRetrieval,
+            SirPhase::ShortTermRetrieval => SirPhase::InterleavedRetrieval,
+            SirPhase::InterleavedRetrieval => SirPhase::MediumSpacing,
+            SirPhase::MediumSpacing | SirPhase::IntegrationTransfer => SirPhase::IntegrationTransfer,
+        }
+    }
+
+    fn demote(phase: &SirPhase) -> SirPhase {
+        match phase {
+            SirPhase::Encoding | SirPhase::ShortTermRetrieval => SirPhase::Encoding,
+            SirPhase::InterleavedRetrieval => SirPhase::ShortTermRetrieval,
+            SirPhase::MediumSpacing => SirPhase::InterleavedRetrieval,
+            SirPhase::IntegrationTransfer => SirPhase::MediumSpacing,
+        }
+    }
+
+    fn phase_score(phase: &SirPhase) -> f64 {
+        match phase {
+            SirPhase::Encoding => 0.0,
+            SirPhase::ShortTermRetrieval => 25.0,
+            SirPhase::InterleavedRetrieval => 50.0,
+            SirPhase::MediumSpacing => 75.0,
+            SirPhase::IntegrationTransfer => 100.0,
+        }
+    }
+
+    /// Mastery (0-100) from recency-weighted accuracy, how far each item has
+    /// climbed the phase ladder, and calibration under `mapping`. Expects
+    /// attempts newest first.
+    pub fn calculate_mastery(&self, attempts: &[Attempt], mapping: &ConfidenceMapping) -> f64 {
+        if attempts.is_empty() {
+            return 0.0;
+        }
+
+        let mut weighted_correct = 0.0;
+        let mut total_weight = 0.0;
+        for (i, attempt) in attempts.iter().enumerate() {
+            let weight = 0.9f64.powi(i as i32);
+            total_weight += weight;
+            if attempt.is_correct {
+                weighted_correct += weight;
+            }
+        }
+        let accuracy = weighted_correct / total_weight * 100.0;
+
+        // Only the latest attempt per item reflects where that item sits on the ladder
+        let mut seen = HashSet::new();
+        let latest_phases: Vec<f64> = attempts
+            .iter()
+            .filter(|a| seen.insert(a.item_id.as_str()))
+            .map(|a| Self::phase_score(&a.sir_phase))
+            .collect();
+        let phase_progress = latest_phases.iter().sum::<f64>() / latest_phases.len() as f64;
+
+        let calibration = (1.0 - self.calculate_brier_score(attempts, mapping)).max(0.0) * 100.0;
+
+        (accuracy * 0.6 + phase_progress * 0.25 + calibration * 0.15).clamp(0.0, 100.0)
+    }
+
+    /// Mean squared gap between the probability `mapping` gives each
+    /// confidence rating and the outcome; 0.5 with no attempts.
+    pub fn calculate_brier_score(&self, attempts: &[Attempt], mapping: &ConfidenceMapping) -> f64 {
+        if attempts.is_empty() {
+            return 0.5;
+        }
+
+        let total = attempts.len() as f64;
+        let sum: f64 = attempts
+            .iter()
+            .map(|a| {
+                let confidence_prob = mapping.probability(a.confidence);
+                let actual = if a.is_correct { 1.0 } else { 0.0 };
+                (confidence_prob - actual).powi(2)
+            })
+            .sum();
+
+        sum / total
+    }
+
+    /// Least-squares slope of performance (0-100) against time over the last
+    /// `TREND_WINDOW_DAYS`, in points per day. Each attempt's performance is
+    /// the mean of its score and the FSRS retrievability of the item when it
+    /// was answered, from the previous review's stability; an item's first
+    /// attempt has no memory state yet and counts its score alone. The trend
+    /// is "improving" or "declining" once the fitted line moves by
+    /// `TREND_THRESHOLD` points across the window, and "stable" otherwise or
+    /// with too little history.
+    pub fn calculate_trend(&self, attempts: &[Attempt], fsrs: &FSRSScheduler, now: DateTime<Utc>) -> (&'static str, f64) {
+        let start = now - Duration::days(TREND_WINDOW_DAYS);
+        let mut chronological: Vec<&Attempt> = attempts.iter().collect();
+        chronological.sort_by_key(|a| a.attempted_at);
+
+        // Time and stability of each item's previous review
+        let mut previous: HashMap<&str, (DateTime<Utc>, f64)> = HashMap::new();
+        let mut points: Vec<(f64, f64)> = Vec::new();
+        for attempt in chronological {
+            let recall = previous
+                .get(attempt.item_id.as_str())
+                .filter(|(_, stability)| *stability > 0.0)
+                .map(|(at, stability)| fsrs.retrievability((attempt.attempted_at - *at).num_days().max(0) as i32, *stability));
+            previous.insert(attempt.item_id.as_str(), (attempt.attempted_at, attempt.stability));
+            if attempt.attempted_at < start || attempt.attempted_at > now {
+                continue;
+            }
+            let performance = recall.map_or(attempt.score, |recall| (attempt.score + recall) / 2.0);
+            points.push(((attempt.attempted_at - start).num_seconds() as f64 / 86_400.0, performance * 100.0));
+        }
+        if points.len() < MIN_TREND_ATTEMPTS {
+            return ("stable", 0.0);
+        }
+
+        let n = points.len() as f64;
+        let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+        let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+        let covariance: f64 = points.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+        let variance: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+        if variance == 0.0 {
+            return ("stable", 0.0);
+        }
+
+        let slope = covariance / variance;
+        let change = slope * TREND_WINDOW_DAYS as f64;
+        let trend = if change >= TREND_THRESHOLD {
+            "improving"
+        } else if change <= -TREND_THRESHOLD {
+            "declining"
+        } else {
+            "stable"
+        };
+        (trend, slope)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attempt_in(phase: SirPhase, is_correct: bool, confidence: i32) -> Attempt {
+        let mut attempt = Attempt::new("item".into(), None, "answer".into(), is_correct, confidence, 1000);
+        attempt.sir_phase = phase;
+        attempt
+    }
+
+    fn reflection(felt_uncertain: bool, felt_confusing: bool, needs_review: bool) -> Option<MetacognitiveReflection> {
+        Some(MetacognitiveReflection {
+            felt_uncertain,
+            felt_confusing,
+            needs_review,
+            notes: None,
+        })
+    }
+
+    fn scheduled_days(attempt: &Attempt) -> i64 {
+        (attempt.next_review_date - attempt.attempted_at).num_days()
+    }
+
+    #[test]
+    fn encoding_success_moves_to_short_term_retrieval() {
+        let sir = SirScheduler::new();
+        let mut attempt = attempt_in(SirPhase::Encoding, true, 3);
+        sir.schedule(&mut attempt, None);
+        assert_eq!(attempt.sir_phase, SirPhase::ShortTermRetrieval);
+        assert!((1..=2).contains(&scheduled_days(&attempt)));
+    }
+
+    #[test]
+    fn short_term_success_moves_to_interleaved_retrieval() {
+        let sir = SirScheduler::new();
+        let mut attempt = attempt_in(SirPhase::ShortTermRetrieval, true, 3);
+        sir.schedule(&mut attempt, None);
+        assert_eq!(attempt.sir_phase, SirPhase::InterleavedRetrieval);
+        assert!((3..=5).contains(&scheduled_days(&attempt)));
+    }
+
+    #[test]
+    fn interleaved_success_moves_to_medium_spacing() {
+        let sir = SirScheduler::new();
+        let mut attempt = attempt_in(SirPhase::InterleavedRetrieval, true, 3);
+        sir.schedule(&mut attempt, None);
+        assert_eq!(attempt.sir_phase, SirPhase::MediumSpacing);
+        assert!((7..=10).contains(&scheduled_days(&attempt)));
+    }
+
+    #[test]
+    fn medium_spacing_success_moves_to_integration_transfer() {
+        let sir = SirScheduler::new();
+        let mut attempt = attempt_in(SirPhase::MediumSpacing, true, 3);
+        sir.schedule(&mut attempt, None);
+        assert_eq!(attempt.sir_phase, SirPhase::IntegrationTransfer);
+        assert!(scheduled_days(&attempt) >= 14);
+    }
+
+    #[test]
+    fn integration_transfer_success_stays_at_top() {
+        let sir = SirScheduler::new();
+        let mut attempt = attempt_in(SirPhase::IntegrationTransfer, true, 5);
+        sir.schedule(&mut attempt, None);
+        assert_eq!(attempt.sir_phase, SirPhase::IntegrationTransfer);
+        assert_eq!(scheduled_days(&attempt), 28);
+    }
+
+    #[test]
+    fn failure_demotes_one_phase() {
+        let sir = SirScheduler::new();
+        let mut attempt = attempt_in(SirPhase::MediumSpacing, false, 2);
+        sir.schedule(&mut attempt, None);
+        assert_eq!(attempt.sir_phase, SirPhase::InterleavedRetrieval);
+        assert!((3..=5).contains(&scheduled_days(&attempt)));
+    }
+
+    #[test]
+    fn failure_from_short_term_returns_to_encoding() {
+        let sir = SirScheduler::new();
+        let mut attempt = attempt_in(SirPhase::ShortTermRetrieval, false, 1);
+        sir.schedule(&mut attempt, None);
+        assert_eq!(attempt.sir_phase, SirPhase::Encoding);
+        assert_eq!(
+            attempt.next_review_date - attempt.attempted_at,
+            Duration::minutes(RELEARN_MINUTES)
+        );
+    }
+
+    #[test]
+    fn confident_failure_resets_to_encoding() {
+        let sir = SirScheduler::new();
+        let mut attempt = attempt_in(SirPhase::IntegrationTransfer, false, 5);
+        sir.schedule(&mut attempt, None);
+        assert_eq!(attempt.sir_phase, SirPhase::Encoding);
+    }
+
+    #[test]
+    fn uncertainty_and_confusion_shorten_interval() {
+        let sir = SirScheduler::new();
+        let mut plain = attempt_in(SirPhase::InterleavedRetrieval, true, 5);
+        sir.schedule(&mut plain, None);
+
+        let mut reflective = attempt_in(SirPhase::InterleavedRetrieval, true, 5);
+        sir.schedule(&mut reflective, reflection(true, true, false));
+
+        assert_eq!(reflective.sir_phase, SirPhase::MediumSpacing);
+        assert!(scheduled_days(&reflective) < scheduled_days(&plain));
+        assert!(reflective.metacognitive.is_some());
+    }
+
+    #[test]
+    fn needs_review_holds_phase_and_caps_interval() {
+        let sir = SirScheduler::new();
+        let mut attempt = attempt_in(SirPhase::MediumSpacing, true, 5);
+        sir.schedule(&mut attempt, reflection(false, false, true));
+        assert_eq!(attempt.sir_phase, SirPhase::MediumSpacing);
+        assert_eq!(scheduled_days(&attempt), 1);
+    }
+
+    #[test]
+    fn is_due_compares_against_next_review_date() {
+        let sir = SirScheduler::new();
+        let mut attempt = attempt_in(SirPhase::Encoding, true, 3);
+        sir.schedule(&mut attempt, None);
+        assert!(!sir.is_due(attempt.next_review_date, Utc::now()));
+
+        attempt.next_review_date = Utc::now() - Duration::minutes(1);
+        assert!(sir.is_due(attempt.next_review_date, Utc::now()));
+    }
+
+    #[test]
+    fn mastery_rewards_correct_attempts_at_later_phases() {
+        let sir = SirScheduler::new();
+        let mapping = ConfidenceMapping::default();
+        assert_eq!(sir.calculate_mastery(&[], &mapping), 0.0);
+
+        let strong = vec![attempt_in(SirPhase::IntegrationTransfer, true, 5)];
+        let weak = vec![attempt_in(SirPhase::Encoding, false, 5)];
+        assert!(sir.calculate_mastery(&strong, &mapping) > sir.calculate_mastery(&weak, &mapping));
+        assert!(sir.calculate_mastery(&strong, &mapping) <= 100.0);
+    }
+
+    #[test]
+    fn brier_score_reads_confidence_through_the_mapping() {
+        let sir = SirScheduler::new();
+        let attempts = vec![attempt_in(SirPhase::Encoding, true, 5), attempt_in(SirPhase::Encoding, false, 1)];
+        let default = ConfidenceMapping::default();
+        assert!((sir.calculate_brier_score(&attempts, &default) - 0.01).abs() < 1e-9);
+
+        let certain = ConfidenceMapping { probabilities: [0.0, 0.25, 0.5, 0.75, 1.0] };
+        assert_eq!(sir.calculate_brier_score(&attempts, &certain), 0.0);
+        assert!(sir.calculate_mastery(&attempts, &certain) > sir.calculate_mastery(&attempts, &default));
+    }
+
+    fn history(now: DateTime<Utc>, outcomes: &[bool]) -> Vec<Attempt> {
+        outcomes
+            .iter()
+            .enumerate()
+            .map(|(i, &is_correct)| {
+                let mut attempt = attempt_in(SirPhase::Encoding, is_correct, 3);
+                attempt.attempted_at = now - Duration::days(2 * (outcomes.len() - i) as i64);
+                attempt
+            })
+            .collect()
+    }
+
+    #[test]
+    fn trend_follows_score_over_the_window() {
+        let sir = SirScheduler::new();
+        let fsrs = FSRSScheduler::default();
+        let now = Utc::now();
+
+        let (trend, slope) = sir.calculate_trend(&history(now, &[false, false, false, true, true, true]), &fsrs, now);
+        assert_eq!(trend, "improving");
+        assert!(slope > 0.0);
+
+        let (trend, slope) = sir.calculate_trend(&history(now, &[true, true, true, false, false, false]), &fsrs, now);
+        assert_eq!(trend, "declining");
+        assert!(slope < 0.0);
+
+        assert_eq!(sir.calculate_trend(&history(now, &[true, false, true, false, true, false, true]), &fsrs, now).0, "stable");
+    }
+
+    #[test]
+    fn trend_falls_as_recall_fades_between_reviews() {
+        let sir = SirScheduler::new();
+        let fsrs = FSRSScheduler::default();
+        let now = Utc::now();
+        // Always right, but each gap is longer than the last at the same stability
+        let attempts: Vec<Attempt> = [29, 28, 26, 22, 14, 0]
+            .iter()
+            .map(|&days_ago| {
+                let mut attempt = attempt_in(SirPhase::Encoding, true, 3);
+                attempt.attempted_at = now - Duration::days(days_ago);
+                attempt.stability = 1.0;
+                attempt
+            })
+            .collect();
+
+        let (trend, slope) = sir.calculate_trend(&attempts, &fsrs, now);
+        assert_eq!(trend, "declining");
+        assert!(slope < 0.0);
+    }
+
+    #[test]
+    fn trend_needs_enough_recent_history() {
+        let sir = SirScheduler::new();
+        let fsrs = FSRSScheduler::default();
+        let now = Utc::now();
+        assert_eq!(sir.calculate_trend(&history(now, &[false, false, true, true]), &fsrs, now), ("stable", 0.0));
+
+        let old = history(now - Duration::days(TREND_WINDOW_DAYS), &[false, false, false, true, true, true]);
+        assert_eq!(sir.calculate_trend(&old, &fsrs, now), ("stable", 0.0));
+    }
+}
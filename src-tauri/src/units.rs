@@ -0,0 +1,182 @@
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum UnitError {
+    #[error("Unknown unit '{0}'")]
+    Unknown(String),
+    #[error("Cannot convert {from} to {to}")]
+    Incompatible { from: String, to: String },
+}
+
+/// Exponents of each base dimension. Base units are g, L, min, mEq, mOsm,
+/// mmol, units and kg of body weight.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+struct Dimensions {
+    mass: i8,
+    volume: i8,
+    time: i8,
+    equivalents: i8,
+    osmoles: i8,
+    moles: i8,
+    units: i8,
+    body_weight: i8,
+}
+
+impl Dimensions {
+    fn combine(self, other: Dimensions, sign: i8) -> Dimensions {
+        Dimensions {
+            mass: self.mass + sign * other.mass,
+            volume: self.volume + sign * other.volume,
+            time: self.time + sign * other.time,
+            equivalents: self.equivalents + sign * other.equivalents,
+            osmoles: self.osmoles + sign * other.osmoles,
+            moles: self.moles + sign * other.moles,
+            units: self.units + sign * other.units,
+            body_weight: self.body_weight + sign * other.body_weight,
+        }
+    }
+}
+
+/// A parsed unit such as `mg`, `mL/hr`, `mEq/L` or `mcg/kg/min`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Unit {
+    factor: f64,
+    dims: Dimensions,
+}
+
+impl Unit {
+    /// Parses a unit string. Everything after the first `/` is a denominator,
+    /// so `mg/kg/day` reads as mg per kg per day. `kg` in a denominator means
+    /// kilograms of body weight, which keeps weight-normalized doses distinct from
+    /// plain masses.
+    pub fn parse(text: &str) -> Result<Unit, UnitError> {
+        let normalized: String = text
+            .trim()
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| if c == 'µ' || c == 'μ' { 'u' } else { c })
+            .collect::<String>()
+            .to_lowercase();
+
+        if normalized.is_empty() {
+            return Ok(Unit { factor: 1.0, dims: Dimensions::default() });
+        }
+        // % is weight/volume: 1% = 1 g per 100 mL
+        if normalized == "%" {
+            return Ok(Unit {
+                factor: 10.0,
+                dims: Dimensions { mass: 1, volume: -1, ..Default::default() },
+            });
+        }
+
+        let mut unit = Unit { factor: 1.0, dims: Dimensions::default() };
+        for (i, token) in normalized.split('/').enumerate() {
+            let is_denominator = i > 0;
+            let (factor, dims) = if is_denominator && token == "kg" {
+                (1.0, Dimensions { body_weight: 1, ..Default::default() })
+            } else {
+                Self::atom(token).ok_or_else(|| UnitError::Unknown(text.trim().to_string()))?
+            };
+
+            if is_denominator {
+                unit.factor /= factor;
+                unit.dims = unit.dims.combine(dims, -1);
+            } else {
+                unit.factor *= factor;
+                unit.dims = unit.dims.combine(dims, 1);
+            }
+        }
+        Ok(unit)
+    }
+
+    pub fn is_compatible(&self, other: &Unit) -> bool {
+        self.dims == other.dims
+    }
+
+    fn atom(token: &str) -> Option<(f64, Dimensions)> {
+        let mass = Dimensions { mass: 1, ..Default::default() };
+        let volume = Dimensions { volume: 1, ..Default::default() };
+        let time = Dimensions { time: 1, ..Default::default() };
+        let equivalents = Dimensions { equivalents: 1, ..Default::default() };
+        let osmoles = Dimensions { osmoles: 1, ..Default::default() };
+        let moles = Dimensions { moles: 1, ..Default::default() };
+        let units = Dimensions { units: 1, ..Default::default() };
+
+        let atom = match token {
+            "ng" => (1e-9, mass),
+            "mcg" | "ug" => (1e-6, mass),
+            "mg" => (1e-3, mass),
+            "g" | "gm" => (1.0, mass),
+            "kg" => (1e3, mass),
+            "ml" | "cc" => (1e-3, volume),
+            "dl" => (0.1, volume),
+            "l" => (1.0, volume),
+            "s" | "sec" => (1.0 / 60.0, time),
+            "min" => (1.0, time),
+            "h" | "hr" | "hour" => (60.0, time),
+            "d" | "day" => (1440.0, time),
+            "wk" | "week" => (10080.0, time),
+            "meq" => (1.0, equivalents),
+            "eq" => (1e3, equivalents),
+            "mosm" => (1.0, osmoles),
+            "osm" => (1e3, osmoles),
+            "umol" => (1e-3, moles),
+            "mmol" => (1.0, moles),
+            "mol" => (1e3, moles),
+            "milliunits" | "mu" => (1e-3, units),
+            "u" | "unit" | "units" | "iu" => (1.0, units),
+            _ => return None,
+        };
+        Some(atom)
+    }
+}
+
+/// Converts `value` expressed in `from` into `to`.
+pub fn convert(value: f64, from: &str, to: &str) -> Result<f64, UnitError> {
+    let from_unit = Unit::parse(from)?;
+    let to_unit = Unit::parse(to)?;
+    if !from_unit.is_compatible(&to_unit) {
+        return Err(UnitError::Incompatible {
+            from: from.trim().to_string(),
+            to: to.trim().to_string(),
+        });
+    }
+    Ok(value * from_unit.factor / to_unit.factor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9 * expected.abs().max(1.0), "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn converts_mass_and_volume_prefixes() {
+        assert_close(convert(0.5, "g", "mg").unwrap(), 500.0);
+        assert_close(convert(250.0, "mg", "g").unwrap(), 0.25);
+        assert_close(convert(1.0, "µg", "ng").unwrap(), 1000.0);
+        assert_close(convert(1.0, "mcg", "µg").unwrap(), 1.0);
+    }
+
+    #[test]
+    fn converts_rates_and_weight_based_doses() {
+        assert_close(convert(100.0, "mL/hr", "mL/min").unwrap(), 100.0 / 60.0);
+        assert_close(convert(5.0, "mcg/kg/min", "mg/kg/hr").unwrap(), 0.3);
+    }
+
+    #[test]
+    fn converts_concentrations_and_percent() {
+        assert_close(convert(2.0, "mg/mL", "g/L").unwrap(), 2.0);
+        assert_close(convert(0.9, "%", "mg/mL").unwrap(), 9.0);
+        assert_close(convert(140.0, "mEq/L", "mEq/dL").unwrap(), 14.0);
+    }
+
+    #[test]
+    fn rejects_unknown_and_incompatible_units() {
+        assert!(matches!(convert(1.0, "mg", "mg/kg"), Err(UnitError::Incompatible { .. })));
+        assert!(matches!(convert(1.0, "mg", "mL"), Err(UnitError::Incompatible { .. })));
+        assert!(matches!(convert(1.0, "foo", "mg"), Err(UnitError::Unknown(_))));
+    }
+}
@@ -1,5 +1,5 @@
 import { invoke } from '@tauri-apps/api/tauri';
-import type { Concept, Item, Attempt, Session, SessionType, ConceptMastery, DailyPlan, PerformanceTrend, LearningMaterial, MetacognitiveReflection } from '@/types';
+import type { Concept, Item, Attempt, Session, SessionType, ConceptMastery, DailyPlan, PerformanceTrend, LearningMaterial, MaterialProgress, MetacognitiveReflection, ItemAnswer, FsrsOptimizationReport, IrtModel, IrtCalibrationReport, ArchiveSummary, RestoreMode, AnkiImportReport, AnkiExportReport, ItemColumnMapping, ItemImportReport, ConceptEdge, ConceptRelation, RootCauseReport, AttemptFilter, ErrorAnalysis, DistractorReport, CalibrationAnalysis, ConfidenceMapping, ExamResults, SessionReport, ConceptAbility } from '@/types';
 
 export const conceptApi = {
   create: async (name: string, domain: string): Promise<Concept> => {
@@ -14,6 +14,18 @@ export const conceptApi = {
   delete: async (id: string): Promise<void> => {
     return await invoke('delete_concept', { id });
   },
+  getItems: async (conceptId: string): Promise<Item[]> => {
+    return await invoke('get_items_by_concept', { conceptId });
+  },
+  addEdge: async (fromConceptId: string, toConceptId: string, relation: ConceptRelation): Promise<ConceptEdge> => {
+    return await invoke('add_concept_edge', { fromConceptId, toConceptId, relation });
+  },
+  removeEdge: async (fromConceptId: string, toConceptId: string, relation: ConceptRelation): Promise<void> => {
+    return await invoke('remove_concept_edge', { fromConceptId, toConceptId, relation });
+  },
+  getEdges: async (): Promise<ConceptEdge[]> => {
+    return await invoke('get_concept_edges');
+  },
 };
 
 export const itemApi = {
@@ -34,14 +46,16 @@ export const itemApi = {
   delete: async (id: string): Promise<void> => {
     return await invoke('delete_item', { id });
   },
+  getConcepts: async (itemId: string): Promise<Concept[]> => {
+    return await invoke('get_concepts_by_item', { itemId });
+  },
 };
 
 export const attemptApi = {
   submit: async (
     itemId: string,
     sessionId: string | null,
-    userAnswer: string,
-    isCorrect: boolean,
+    answer: ItemAnswer,
     confidence: number,
     timeSpentMs: number,
     metacognitive?: MetacognitiveReflection
@@ -49,8 +63,7 @@ export const attemptApi = {
     return await invoke('submit_attempt', { 
       itemId,  // Tauri converts to item_id
       sessionId,  // Tauri converts to session_id
-      userAnswer,  // Tauri converts to user_answer
-      isCorrect,  // Tauri converts to is_correct
+      answer,  // graded server-side
       confidence, 
       timeSpentMs,  // Tauri converts to time_spent_ms
       metacognitive
@@ -61,6 +74,15 @@ export const attemptApi = {
   },
 };
 
+export const schedulerApi = {
+  optimizeFsrs: async (): Promise<FsrsOptimizationReport> => {
+    return await invoke('optimize_fsrs_parameters');
+  },
+  calibrateItems: async (model?: IrtModel): Promise<IrtCalibrationReport> => {
+    return await invoke('calibrate_items', { model });
+  },
+};
+
 export const sessionApi = {
   create: async (sessionType: SessionType, totalItems: number): Promise<Session> => {
     return await invoke('create_session', { 
@@ -68,29 +90,62 @@ export const sessionApi = {
       totalItems  // Tauri converts to total_items
     });
   },
-  complete: async (sessionId: string, completedItems: number, accuracy: number, averageConfidence: number): Promise<void> => {
+  complete: async (sessionId: string): Promise<Session> => {
     return await invoke('complete_session', { 
-      sessionId,  // Tauri converts to session_id
-      completedItems,  // Tauri converts to completed_items
-      accuracy, 
-      averageConfidence  // Tauri converts to average_confidence
+      sessionId  // Tauri converts to session_id
     });
   },
+  getReport: async (sessionId: string): Promise<SessionReport> => {
+    return await invoke('get_session_report', { sessionId });
+  },
   getAll: async (): Promise<Session[]> => {
     return await invoke('get_all_sessions');
   },
+  getItems: async (sessionId: string): Promise<Item[]> => {
+    return await invoke('get_session_items', { sessionId });
+  },
+  getExamResults: async (sessionId: string): Promise<ExamResults> => {
+    return await invoke('get_exam_results', { sessionId });
+  },
+  getNextDiagnosticItem: async (sessionId: string): Promise<Item | null> => {
+    return await invoke('get_next_diagnostic_item', { sessionId });
+  },
+  getNextFocusedItem: async (sessionId: string): Promise<Item | null> => {
+    return await invoke('get_next_focused_item', { sessionId });
+  },
 };
 
 export const analyticsApi = {
   getConceptMastery: async (): Promise<ConceptMastery[]> => {
     return await invoke('get_concept_mastery');
   },
-  getDailyPlan: async (): Promise<DailyPlan> => {
-    return await invoke('get_daily_plan');
+  getDailyPlan: async (budgetMinutes?: number): Promise<DailyPlan> => {
+    return await invoke('get_daily_plan', { budgetMinutes });
   },
   getPerformanceTrends: async (): Promise<PerformanceTrend[]> => {
     return await invoke('get_performance_trends');
   },
+  getRootCauses: async (conceptId: string, threshold?: number): Promise<RootCauseReport> => {
+    return await invoke('get_root_cause_analysis', { conceptId, threshold });
+  },
+  getConceptAbilities: async (): Promise<ConceptAbility[]> => {
+    return await invoke('get_concept_abilities');
+  },
+  getErrorAnalysis: async (filter: AttemptFilter = {}): Promise<ErrorAnalysis[]> => {
+    return await invoke('get_error_analysis', { filter });
+  },
+  getDistractorAnalysis: async (itemId?: string): Promise<DistractorReport[]> => {
+    return await invoke('get_distractor_analysis', { itemId });  // Tauri converts to item_id
+  },
+  getCalibration: async (filter: AttemptFilter = {}): Promise<CalibrationAnalysis> => {
+    return await invoke('get_calibration', { filter });
+  },
+  getConfidenceMapping: async (): Promise<ConfidenceMapping> => {
+    return await invoke('get_confidence_mapping');
+  },
+  setConfidenceMapping: async (mapping: ConfidenceMapping): Promise<void> => {
+    return await invoke('set_confidence_mapping', { mapping });
+  },
 };
 
 export const learningMaterialApi = {
@@ -100,15 +155,64 @@ export const learningMaterialApi = {
   get: async (id: string): Promise<LearningMaterial | null> => {
     return await invoke('get_learning_material', { id });
   },
+  getAll: async (domain?: string): Promise<LearningMaterial[]> => {
+    return await invoke('get_all_learning_materials', { domain });
+  },
+  search: async (query: string): Promise<LearningMaterial[]> => {
+    return await invoke('search_learning_materials', { query });
+  },
+  update: async (material: LearningMaterial): Promise<void> => {
+    return await invoke('update_learning_material', { material });
+  },
+  delete: async (id: string): Promise<void> => {
+    return await invoke('delete_learning_material', { id });
+  },
+  getConcepts: async (materialId: string): Promise<Concept[]> => {
+    return await invoke('get_concepts_by_material', { materialId });  // Tauri converts to material_id
+  },
+  getItems: async (materialId: string): Promise<Item[]> => {
+    return await invoke('get_items_by_material', { materialId });
+  },
+  getProgress: async (materialId?: string): Promise<MaterialProgress[]> => {
+    return await invoke('get_material_progress', { materialId });
+  },
 };
 
 export const importApi = {
   conceptsFromCsv: async (csvContent: string): Promise<Concept[]> => {
     return await invoke('import_concepts_from_csv', { csvContent });
   },
+  itemsFromCsv: async (
+    content: string,
+    mapping: ItemColumnMapping,
+    defaultDomain: string,
+    dryRun: boolean
+  ): Promise<ItemImportReport> => {
+    return await invoke('import_items_from_csv', { content, mapping, defaultDomain, dryRun });
+  },
+  ankiDeck: async (path: string): Promise<AnkiImportReport> => {
+    return await invoke('import_anki_deck', { path });
+  },
+  exportAnkiDeck: async (
+    destination: string,
+    conceptIds: string[],
+    itemIds: string[],
+    includeHistory: boolean
+  ): Promise<AnkiExportReport> => {
+    return await invoke('export_anki_deck', { destination, conceptIds, itemIds, includeHistory });
+  },
 };
 
 export const databaseApi = {
+  backup: async (destination: string): Promise<void> => {
+    return await invoke('backup_database', { destination });
+  },
+  exportCollection: async (destination: string): Promise<ArchiveSummary> => {
+    return await invoke('export_collection', { destination });
+  },
+  restoreCollection: async (source: string, mode: RestoreMode): Promise<ArchiveSummary> => {
+    return await invoke('restore_collection', { source, mode });
+  },
   clearAll: async (): Promise<void> => {
     return await invoke('clear_all_data');
   },
@@ -130,6 +234,7 @@ export const api = {
   concepts: conceptApi,
   items: itemApi,
   attempts: attemptApi,
+  scheduler: schedulerApi,
   sessions: sessionApi,
   analytics: analyticsApi,
   learningMaterials: learningMaterialApi,
@@ -10,6 +10,7 @@ import type {
   ConceptMastery,
   DailyPlan,
   MetacognitiveReflection,
+  ItemAnswer,
 } from '@/types';
 
 interface AppState {
@@ -40,9 +41,9 @@ interface AppState {
   deleteConcept: (id: string) => Promise<void>;
   createItem: (stem: string, itemType: any, conceptIds: string[], explanation: string) => Promise<void>;
   deleteItem: (id: string) => Promise<void>;
-  submitAttempt: (itemId: string, sessionId: string | null, userAnswer: string, isCorrect: boolean, confidence: number, timeSpent: number, metacognitive?: MetacognitiveReflection) => Promise<void>;
+  submitAttempt: (itemId: string, sessionId: string | null, answer: ItemAnswer, confidence: number, timeSpent: number, metacognitive?: MetacognitiveReflection) => Promise<void>;
   createSession: (sessionType: any, totalItems: number) => Promise<void>;
-  completeCurrentSession: (completedItems: number, accuracy: number, avgConfidence: number) => Promise<void>;
+  completeCurrentSession: () => Promise<void>;
   clearDatabase: () => Promise<void>;
 
   // UI Actions
@@ -162,9 +163,9 @@ export const useStore = create<AppState>((set, get) => ({
     }
   },
 
-  submitAttempt: async (itemId: string, sessionId: string | null, userAnswer: string, isCorrect: boolean, confidence: number, timeSpent: number, metacognitive?: MetacognitiveReflection) => {
+  submitAttempt: async (itemId: string, sessionId: string | null, answer: ItemAnswer, confidence: number, timeSpent: number, metacognitive?: MetacognitiveReflection) => {
     try {
-      await api.attempts.submit(itemId, sessionId, userAnswer, isCorrect, confidence, timeSpent, metacognitive);
+      await api.attempts.submit(itemId, sessionId, answer, confidence, timeSpent, metacognitive);
       await get().loadConceptMastery();
       await get().loadDailyPlan();
     } catch (error: any) {
@@ -181,11 +182,11 @@ export const useStore = create<AppState>((set, get) => ({
     }
   },
 
-  completeCurrentSession: async (completedItems: number, accuracy: number, avgConfidence: number) => {
+  completeCurrentSession: async () => {
     try {
       const session = get().currentSession;
       if (session) {
-        await api.sessions.complete(session.id, completedItems, accuracy, avgConfidence);
+        await api.sessions.complete(session.id);
         set({ currentSession: null });
         await get().loadSessions();
       }
@@ -253,7 +254,7 @@ export const useStore = create<AppState>((set, get) => ({
         conceptId: c.concept_id,
         conceptName: c.concept_name,
         mastery: c.mastery_score,
-        trend: c.trend as 'up' | 'down' | 'stable',
+        trend: c.trend === 'improving' ? 'up' : c.trend === 'declining' ? 'down' : 'stable',
         itemsToReview: c.attempts,
       }));
   },
@@ -20,6 +20,15 @@ export interface Concept {
   updated_at: string;
 }
 
+export type ConceptRelation = 'prerequisite' | 'part-of' | 'related';
+
+export interface ConceptEdge {
+  from_concept_id: string;
+  to_concept_id: string;
+  relation: ConceptRelation;
+  created_at: string;
+}
+
 export type ItemType = 'mcq' | 'free-recall' | 'calc' | 'case' | 'cloze';
 
 export interface Item {
@@ -74,6 +83,25 @@ export interface MetacognitiveReflection {
   notes?: string;
 }
 
+export type ItemAnswer =
+  | { type: 'mcq'; option_id: string }
+  | { type: 'free-recall'; text: string }
+  | { type: 'calc'; value: number; unit: string }
+  | { type: 'case'; steps: { step_number: number; answer: string }[] }
+  | { type: 'cloze'; blanks: { [blankId: string]: string } };
+
+export type AnswerError = 'WrongUnit' | 'IncompatibleUnit' | 'UnknownUnit' | 'OutOfTolerance';
+
+export interface PartFeedback {
+  part_id: string;
+  is_correct: boolean;
+  points_awarded: number;
+  points_possible: number;
+  expected: string;
+  message?: string;
+  error?: AnswerError | null; // calculation parts: why the answer missed
+}
+
 export interface Attempt {
   id: string;
   item_id: string;
@@ -86,6 +114,8 @@ export interface Attempt {
   sir_phase: SirPhase;
   next_review_date: string;
   metacognitive?: MetacognitiveReflection;
+  score: number; // 0-1
+  feedback: PartFeedback[];
   // Legacy FSRS fields
   stability: number;
   difficulty: number;
@@ -96,7 +126,16 @@ export interface Attempt {
 
 export type ReviewState = 'New' | 'Learning' | 'Review' | 'Relearning';
 
-export type SessionType = 'Mixed' | 'Diagnostic' | { Focused: { concept_id: string } } | { Exam: { time_limit_ms: number } };
+export type FocusScope = 'concept' | 'tags' | 'domain';
+
+export interface FocusedSession {
+  concept_id: string;
+  scope?: FocusScope; // defaults to 'concept'
+  target_accuracy?: number | null; // 0-1 over the latest answers; default 0.8
+  target_mastery?: number | null; // 0-100; default 80
+}
+
+export type SessionType = 'Mixed' | 'Diagnostic' | { Focused: FocusedSession } | { Exam: { time_limit_ms: number } };
 
 export interface Session {
   id: string;
@@ -109,6 +148,69 @@ export interface Session {
   average_confidence: number;
 }
 
+export interface SessionConceptStats {
+  concept_id: string;
+  concept_name: string;
+  attempts: number;
+  correct: number;
+  accuracy: number;
+  avg_time_ms: number;
+}
+
+export interface SessionMiss {
+  attempt_id: string;
+  item_id: string;
+  stem: string;
+  user_answer: string;
+  expected: string[];
+  confidence: number;
+  time_spent_ms: number;
+}
+
+export interface SessionReport {
+  session: Session;
+  attempts: number;
+  correct: number;
+  accuracy: number;
+  total_time_ms: number;
+  avg_time_ms: number;
+  calibration: CalibrationReport;
+  by_concept: SessionConceptStats[];
+  misses: SessionMiss[];
+}
+
+export interface ConceptAbility {
+  concept_id: string;
+  // Logits; 0 matches an item of difficulty 50
+  ability: number;
+  standard_error: number;
+  items_used: number;
+  session_id?: string;
+  estimated_at: string;
+}
+
+export interface ExamBreakdown {
+  key: string;
+  label: string;
+  items: number;
+  answered: number;
+  correct: number;
+  accuracy: number;
+}
+
+export interface ExamResults {
+  session: Session;
+  deadline: string;
+  timed_out: boolean;
+  total_items: number;
+  answered: number;
+  correct: number;
+  score: number;
+  by_concept: ExamBreakdown[];
+  by_domain: ExamBreakdown[];
+  attempts: Attempt[];
+}
+
 // Analytics types
 export interface PerformanceMetrics {
   accuracy: number;
@@ -133,7 +235,105 @@ export interface ConceptMastery {
   brier_score: number;
   last_attempted?: string;
   stability: number;
-  trend: string;
+  trend: 'improving' | 'declining' | 'stable';
+  trend_slope: number;
+  trend_window_days: number;
+}
+
+export interface MaterialProgress {
+  material_id: string;
+  domain: string;
+  concept_count: number;
+  attempted_concepts: number;
+  weak_concepts: number;
+  mastery_score: number; // 0-100, mean over linked concepts
+  concepts: ConceptMastery[]; // weakest first
+}
+
+export interface RootCause {
+  concept_id: string;
+  concept_name: string;
+  mastery_score: number;
+  attempts: number;
+  depth: number;
+  path: string[];
+}
+
+export interface RootCauseReport {
+  concept_id: string;
+  concept_name: string;
+  mastery_score: number;
+  threshold: number;
+  is_weak: boolean;
+  root_causes: RootCause[];
+}
+
+export interface AttemptFilter {
+  concept_id?: string;
+  domain?: string;
+  from?: string;
+  to?: string;
+}
+
+export interface ErrorAnalysis {
+  error_type: string;
+  count: number;
+  percentage: number;
+  example_items: string[];
+}
+
+export interface OptionStats {
+  option_id: string;
+  text: string;
+  is_correct: boolean;
+  selections: number;
+  selection_rate: number; // of all attempts on the item
+  high_mastery_selections: number;
+  high_mastery_rate: number; // of high-mastery attempts on the item
+  flag: 'non_functioning' | 'chosen_over_key' | null;
+}
+
+export interface DistractorReport {
+  item_id: string;
+  stem: string;
+  attempts: number;
+  high_mastery_attempts: number;
+  unmatched: number;
+  options: OptionStats[];
+  flags: string[];
+}
+
+export interface ConfidenceMapping {
+  // Probability of a correct answer for confidence 1-5
+  probabilities: [number, number, number, number, number];
+}
+
+export interface CalibrationBin {
+  confidence: number;
+  forecast: number;
+  observed: number;
+  count: number;
+}
+
+export interface CalibrationReport {
+  key: string;
+  label: string;
+  attempts: number;
+  brier_score: number;
+  reliability: number;
+  resolution: number;
+  uncertainty: number;
+  overconfidence: number;
+  underconfidence: number;
+  bins: CalibrationBin[];
+}
+
+export interface CalibrationAnalysis {
+  mapping: ConfidenceMapping;
+  overall: CalibrationReport;
+  by_concept: CalibrationReport[];
+  by_domain: CalibrationReport[];
+  by_item_type: CalibrationReport[];
 }
 
 export interface DailyPlan {
@@ -143,6 +343,9 @@ export interface DailyPlan {
   total_items: number;
   estimated_time_min: number;
   coverage_percent: number;
+  budget_min: number;
+  items: PlannedItem[];
+  overflow: PlannedItem[];
 }
 
 export interface PlannedItem {
@@ -150,6 +353,10 @@ export interface PlannedItem {
   concept_id: string;
   reason: string;
   priority: number;
+  domain: string;
+  estimated_time_ms: number;
+  retrievability?: number;
+  due_date?: string;
 }
 
 export interface PerformanceTrend {
@@ -159,6 +366,94 @@ export interface PerformanceTrend {
   avg_confidence: number;
 }
 
+export interface FsrsOptimizationReport {
+  reviews_used: number;
+  log_loss_before: number;
+  log_loss_after: number;
+  rmse_before: number;
+  rmse_after: number;
+  weights: number[];
+  applied: boolean;
+}
+
+export type IrtModel = '1pl' | '2pl';
+
+export interface ItemCalibration {
+  item_id: string;
+  stem: string;
+  responses: number;
+  proportion_correct: number;
+  difficulty_before: number;
+  difficulty: number; // 0-100
+  discrimination_before: number;
+  discrimination: number;
+  flag: 'negative_discrimination' | 'low_discrimination' | null;
+}
+
+export interface IrtCalibrationReport {
+  model: IrtModel;
+  responses_used: number;
+  occasions: number;
+  items: ItemCalibration[]; // flagged first
+  flagged: number;
+  skipped: number;
+}
+
+export type RestoreMode = 'replace' | 'merge';
+
+export interface ArchiveSummary {
+  schema_version: number;
+  created_at: string;
+  checksum: string;
+  learning_materials: number;
+  concepts: number;
+  items: number;
+  attempts: number;
+  sessions: number;
+}
+
+export interface AnkiImportReport {
+  items_created: number;
+  concepts_created: number;
+  attempts_created: number;
+  notes_skipped: number;
+  notes_unsupported: number;
+}
+
+export interface AnkiExportReport {
+  notes_exported: number;
+  cards_exported: number;
+  reviews_exported: number;
+  items_skipped: number;
+}
+
+// Header names of the columns holding each item field
+export interface ItemColumnMapping {
+  stem: string;
+  correct_answer: string;
+  item_type?: string;
+  options?: string;
+  explanation?: string;
+  difficulty?: string;
+  source?: string;
+  concepts?: string;
+}
+
+export interface ImportRowError {
+  row: number;
+  column: string | null;
+  message: string;
+}
+
+export interface ItemImportReport {
+  dry_run: boolean;
+  rows: number;
+  valid_rows: number;
+  items_created: number;
+  new_concepts: string[];
+  errors: ImportRowError[];
+}
+
 // Pharmacy-specific types
 export interface DrugClass {
   id: string;